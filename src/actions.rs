@@ -1,15 +1,173 @@
-use std::collections::HashSet;
-use std::convert::TryInto;
-use std::io::BufRead;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::os::unix::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
+use chrono::{offset, DateTime};
 use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 
 use crate::cache;
+use crate::caching_client;
+use crate::checksum::{self, DigestCache};
 use crate::error::{Error, Result};
-use crate::sdkm_l3::L3Repo;
+use crate::lockfile::{LockedPackage, Lockfile};
+use crate::output::{self, OutputFormat};
+use crate::sdkm::RemoteRepo;
+use crate::sdkm_config::SdkmConfig;
+use crate::sdkm_l1::L1Repo;
+use crate::sdkm_l2::{L2Release, L2Repo};
+use crate::sdkm_l3::{
+    L3Component, L3ComponentVersion, L3ComponentVersionDownloadFile, L3Group, L3Repo, L3Section,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListKind {
+    Categories,
+    Targets,
+    Releases,
+}
+
+impl ListKind {
+    pub fn variants() -> Vec<String> {
+        vec![
+            "categories".to_string(),
+            "targets".to_string(),
+            "releases".to_string(),
+        ]
+    }
+}
+
+impl FromStr for ListKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "categories" => Ok(ListKind::Categories),
+            "targets" => Ok(ListKind::Targets),
+            "releases" => Ok(ListKind::Releases),
+            _ => Err(Error::InvalidListKind(s.to_string(), Self::variants())),
+        }
+    }
+}
+
+/// Input for an external downloader, listing the repo's packages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// An aria2c input file, for `aria2c -i`
+    Aria2,
+    /// A shell script downloading with curl and checking the checksums
+    Shell,
+}
+
+impl ExportFormat {
+    pub fn variants() -> Vec<String> {
+        vec!["aria2".to_string(), "shell".to_string()]
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "aria2" => Ok(ExportFormat::Aria2),
+            "shell" => Ok(ExportFormat::Shell),
+            _ => Err(Error::InvalidExportFormat(s.to_string(), Self::variants())),
+        }
+    }
+}
+
+/// How fetched packages are arranged in the output directory
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Every package directly in the output directory
+    Flat,
+    /// Packages under <component>/<version>/
+    Hierarchical,
+}
+
+impl Layout {
+    pub fn variants() -> Vec<String> {
+        vec!["flat".to_string(), "hierarchical".to_string()]
+    }
+
+    /// Where a package file of a component version goes under `dir`.  The
+    /// names come from the repo, so any that could reach outside `dir` are
+    /// refused.
+    pub fn package_path(
+        self,
+        dir: &Path,
+        component_id: &str,
+        version: &str,
+        file_name: &str,
+    ) -> Result<PathBuf> {
+        match self {
+            Layout::Flat => Ok(dir.join(safe_path_component(file_name)?)),
+            Layout::Hierarchical => Ok(dir
+                .join(safe_path_component(component_id)?)
+                .join(safe_path_component(version)?)
+                .join(safe_path_component(file_name)?)),
+        }
+    }
+}
+
+/// `name` if it is a single, plain path component, also once percent
+/// decoded
+pub fn safe_path_component(name: &str) -> Result<&str> {
+    let decoded = percent_encoding::percent_decode_str(name).decode_utf8_lossy();
+    let unsafe_name = decoded.is_empty()
+        || decoded == "."
+        || decoded == ".."
+        || decoded.contains(['/', '\\', '\0']);
+    if unsafe_name {
+        return Err(Error::UnsafeFileName(name.to_string()));
+    }
+    Ok(name)
+}
+
+impl FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Layout::Flat),
+            "hierarchical" => Ok(Layout::Hierarchical),
+            _ => Err(Error::InvalidLayout(s.to_string(), Self::variants())),
+        }
+    }
+}
+
+/// Order of the components in the full show listing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComponentSort {
+    Id,
+    Name,
+    /// Largest install size first
+    Size,
+}
+
+impl ComponentSort {
+    pub fn variants() -> Vec<String> {
+        vec!["id".to_string(), "name".to_string(), "size".to_string()]
+    }
+}
+
+impl FromStr for ComponentSort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(ComponentSort::Id),
+            "name" => Ok(ComponentSort::Name),
+            "size" => Ok(ComponentSort::Size),
+            _ => Err(Error::InvalidSort(s.to_string(), Self::variants())),
+        }
+    }
+}
 
 #[derive(Debug, StructOpt)]
 pub enum Action {
@@ -30,6 +188,41 @@ pub enum Action {
         /// to the component name.
         #[structopt(short, long)]
         component: Vec<String>,
+
+        /// File listing more components to select, one per line, or - for
+        /// stdin.  Lines starting with # are comments.
+        #[structopt(long)]
+        components_from: Option<PathBuf>,
+
+        /// Version of the selected components that don't name one with
+        /// ":<version>", or a range such as ">=5.1,<6" to take the highest
+        /// version in.  Ranges compare versions as semver, and also work
+        /// after ":".
+        #[structopt(long)]
+        version: Option<String>,
+
+        /// Include components and sections the SDK Manager hides
+        #[structopt(long)]
+        include_hidden: bool,
+
+        /// Only components whose version supports this target hardware id.
+        /// With no other selection, every such component is selected.
+        #[structopt(long)]
+        target_hw: Option<String>,
+
+        /// Order of the full component listing: id, name, or size (the
+        /// first version's install size, largest first)
+        #[structopt(long, default_value = "id")]
+        sort: ComponentSort,
+
+        /// Only list components of this type
+        #[structopt(long)]
+        filter_type: Option<String>,
+
+        /// Print the dependencies of the selected components as a tree
+        /// instead of their details
+        #[structopt(long)]
+        tree: bool,
     },
     /// Fetch packages belonging to specified section, group, or component
     Fetch {
@@ -48,6 +241,97 @@ pub enum Action {
         /// to the component name.
         #[structopt(short, long)]
         component: Vec<String>,
+
+        /// File listing more components to select, one per line, or - for
+        /// stdin.  Lines starting with # are comments.
+        #[structopt(long)]
+        components_from: Option<PathBuf>,
+
+        /// Version of the selected components that don't name one with
+        /// ":<version>", or a range such as ">=5.1,<6" to take the highest
+        /// version in.  Ranges compare versions as semver, and also work
+        /// after ":".
+        #[structopt(long)]
+        version: Option<String>,
+
+        /// Include components and sections the SDK Manager hides
+        #[structopt(long)]
+        include_hidden: bool,
+
+        /// Only components whose version supports this target hardware id.
+        /// With no other selection, every such component is selected.
+        #[structopt(long)]
+        target_hw: Option<String>,
+
+        /// Operating system to fetch OS specific package variants for,
+        /// as listed in the component's operating systems
+        #[structopt(long)]
+        os: Option<String>,
+
+        /// Copy packages out of the http cache instead of symlinking them
+        #[structopt(long)]
+        copy: bool,
+
+        /// After fetching, replace the symlinks in the output directory
+        /// with hard links to the cached packages, or copies where hard
+        /// links aren't possible, so each file stands on its own
+        #[structopt(long)]
+        flatten_symlinks: bool,
+
+        /// Directory to place fetched packages in
+        /// Default is the cache directory
+        #[structopt(short, long, parse(from_os_str))]
+        output_dir: Option<PathBuf>,
+
+        /// Store identical packages only once in the http cache, keyed by
+        /// their checksum
+        #[structopt(long)]
+        dedup: bool,
+
+        /// Log and skip packages that fail instead of stopping at the first
+        #[structopt(short, long)]
+        keep_going: bool,
+
+        /// Skip packages already in the output directory with a valid
+        /// checksum
+        #[structopt(long)]
+        only_missing: bool,
+
+        /// Do nothing if the release's build and revision are the same as
+        /// at the last fetch that completed without failures
+        #[structopt(long)]
+        skip_if_unchanged: bool,
+
+        /// Package arrangement: flat, or hierarchical to place packages
+        /// under <component>/<version>/ and avoid name collisions
+        #[structopt(long, default_value = "flat")]
+        layout: Layout,
+
+        /// Megabytes of disk space to leave free.  Checked before each
+        /// package, stopping the fetch rather than going below it.
+        #[structopt(long)]
+        min_free_space: Option<u64>,
+
+        /// Lockfile recording the release and exact packages fetched.  Its
+        /// release selection is used when none is given.
+        #[structopt(long, parse(from_os_str))]
+        lockfile: Option<PathBuf>,
+
+        /// Fetch exactly the packages in --lockfile, failing if any of
+        /// them changed upstream
+        #[structopt(long, requires = "lockfile")]
+        locked: bool,
+
+        /// Print how each selected package's download url is resolved and
+        /// exit without downloading anything
+        #[structopt(long)]
+        explain: bool,
+
+        /// Print the fetch plan as JSON and exit without downloading
+        /// anything: each selected component's version and its packages'
+        /// urls, sizes, and checksums
+        #[structopt(long, conflicts_with = "explain")]
+        components_json: bool,
     },
     /// Verify local cache of packages belonging to specified section, group, or component
     Verify {
@@ -66,282 +350,2161 @@ pub enum Action {
         /// to the component name.
         #[structopt(short, long)]
         component: Vec<String>,
+
+        /// File listing more components to select, one per line, or - for
+        /// stdin.  Lines starting with # are comments.
+        #[structopt(long)]
+        components_from: Option<PathBuf>,
+
+        /// Version of the selected components that don't name one with
+        /// ":<version>", or a range such as ">=5.1,<6" to take the highest
+        /// version in.  Ranges compare versions as semver, and also work
+        /// after ":".
+        #[structopt(long)]
+        version: Option<String>,
+
+        /// Log and skip packages that fail instead of stopping at the first
+        #[structopt(short, long)]
+        keep_going: bool,
+
+        /// Package arrangement the packages were fetched with: flat or
+        /// hierarchical
+        #[structopt(long, default_value = "flat")]
+        layout: Layout,
+
+        /// Operating system to verify OS specific package variants for,
+        /// as listed in the component's operating systems
+        #[structopt(long)]
+        os: Option<String>,
+
+        /// Download invalid or missing packages again and re-verify them
+        #[structopt(long)]
+        repair: bool,
+
+        /// Hash every package again, even those unchanged since the last
+        /// verify
+        #[structopt(long)]
+        force: bool,
+
+        /// Only check the digests of packages that are present.  Missing
+        /// packages are skipped, so only a digest mismatch fails verify;
+        /// without this a missing package fails it too.
+        #[structopt(long, conflicts_with = "repair")]
+        checksum_only: bool,
+
+        /// Stop at the first package with a checksum type there's no
+        /// verifier for.  Without this they're logged and skipped.
+        #[structopt(long)]
+        strict: bool,
+
+        /// Only check that each package exists with the size the repo
+        /// declares, without hashing it.  A quick first check of a large
+        /// cache.
+        #[structopt(long)]
+        size_only: bool,
+
+        /// Print how each selected package's download url is resolved and
+        /// exit without verifying anything
+        #[structopt(long)]
+        explain: bool,
+    },
+    /// Search package sections, groups, and components for a keyword
+    Search {
+        /// Text to look for (case-insensitive) in ids, names, and descriptions
+        query: String,
+    },
+    /// List the legal values for --product-category, --target-os, or --release
+    List {
+        /// What to list: categories, targets (needs --product-category), or
+        /// releases (needs --product-category and --target-os)
+        what: ListKind,
+    },
+    /// Check the main repo is reachable and the cache is healthy
+    Doctor,
+    /// Print the checksum types, output formats, and cache compression
+    /// modes this build supports, as JSON (or yaml with --format yaml)
+    Capabilities,
+    /// Remove broken http cache entries: those missing their data or
+    /// metadata
+    Clean {
+        /// Also remove entries whose metadata can't be read
+        #[structopt(long)]
+        corrupt: bool,
+    },
+    /// Download the release notes of the selected release into its cache
+    /// directory, when the repo offers them for download
+    Notes,
+    /// Print every package of the release as input for an external
+    /// downloader
+    Export {
+        /// What to write: aria2 (an aria2c input file) or shell (a curl
+        /// script that also checks the checksums)
+        format: ExportFormat,
     },
 }
 
 impl Action {
-    pub fn get_sections(&self) -> &Vec<String> {
+    pub fn get_sections(&self) -> &[String] {
         match self {
-            Action::Show { section, .. } => &section,
-            Action::Fetch { section, .. } => &section,
-            Action::Verify { section, .. } => &section,
+            Action::Show { section, .. } => section,
+            Action::Fetch { section, .. } => section,
+            Action::Verify { section, .. } => section,
+            Action::Search { .. } => &[],
+            Action::List { .. } => &[],
+            Action::Doctor => &[],
+            Action::Capabilities => &[],
+            Action::Clean { .. } => &[],
+            Action::Notes => &[],
+            Action::Export { .. } => &[],
         }
     }
 
-    pub fn get_groups(&self) -> &Vec<String> {
+    pub fn get_groups(&self) -> &[String] {
         match self {
-            Action::Show { group, .. } => &group,
-            Action::Fetch { group, .. } => &group,
-            Action::Verify { group, .. } => &group,
+            Action::Show { group, .. } => group,
+            Action::Fetch { group, .. } => group,
+            Action::Verify { group, .. } => group,
+            Action::Search { .. } => &[],
+            Action::List { .. } => &[],
+            Action::Doctor => &[],
+            Action::Capabilities => &[],
+            Action::Clean { .. } => &[],
+            Action::Notes => &[],
+            Action::Export { .. } => &[],
         }
     }
 
-    pub fn get_components(&self) -> &Vec<String> {
+    pub fn get_components(&self) -> &[String] {
         match self {
-            Action::Show { component, .. } => &component,
-            Action::Fetch { component, .. } => &component,
-            Action::Verify { component, .. } => &component,
+            Action::Show { component, .. } => component,
+            Action::Fetch { component, .. } => component,
+            Action::Verify { component, .. } => component,
+            Action::Search { .. } => &[],
+            Action::List { .. } => &[],
+            Action::Doctor => &[],
+            Action::Capabilities => &[],
+            Action::Clean { .. } => &[],
+            Action::Notes => &[],
+            Action::Export { .. } => &[],
         }
     }
-}
 
-fn get_component_ids(l3repo: &L3Repo, action_data: &Action) -> HashSet<(String, Option<String>)> {
-    let mut component_ids: HashSet<String> = action_data
-        .get_components()
-        .iter()
-        .map(|c| c.to_string())
-        .collect();
-    for section in action_data.get_sections() {
-        component_ids.extend(l3repo.get_components_for_section(&section).into_iter());
+    /// Add the components listed in the --components-from file to the
+    /// selected components.  `stdin_taken_by` names the option already
+    /// reading stdin, if any, which - can't then share.
+    pub fn read_components_from(&mut self, stdin_taken_by: Option<&str>) -> Result<()> {
+        let (path, component) = match self {
+            Action::Show {
+                components_from: Some(path),
+                component,
+                ..
+            }
+            | Action::Fetch {
+                components_from: Some(path),
+                component,
+                ..
+            }
+            | Action::Verify {
+                components_from: Some(path),
+                component,
+                ..
+            } => (path, component),
+            _ => return Ok(()),
+        };
+        let from_stdin = path.as_path() == Path::new("-");
+        let list = if from_stdin {
+            if let Some(other) = stdin_taken_by {
+                return Err(Error::StdinConflict(other.to_string()));
+            }
+            let mut list = String::new();
+            std::io::Read::read_to_string(&mut std::io::stdin(), &mut list)?;
+            list
+        } else {
+            std::fs::read_to_string(&path)?
+        };
+        let listed: Vec<String> = list
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        // Most likely the pipe feeding it failed
+        if from_stdin && listed.is_empty() {
+            return Err(Error::NoComponentsListed("stdin".to_string()));
+        }
+        component.extend(listed);
+        Ok(())
     }
-    for group in action_data.get_groups() {
-        component_ids.extend(l3repo.get_components_for_group(&group).into_iter());
+
+    pub fn include_hidden(&self) -> bool {
+        match self {
+            Action::Show { include_hidden, .. } => *include_hidden,
+            Action::Fetch { include_hidden, .. } => *include_hidden,
+            Action::Verify { .. } => true,
+            Action::Search { .. } => true,
+            Action::List { .. } => true,
+            Action::Doctor => true,
+            Action::Capabilities => true,
+            Action::Clean { .. } => true,
+            Action::Notes => true,
+            Action::Export { .. } => true,
+        }
     }
-    component_ids
-        .iter()
-        .map(|c| {
-            // Check for a version token
-            c.find(':')
-                // split version off from component name
-                .map(|off| c.split_at(off))
-                // convert to String, Some(String)
-                .map(|(a, b)| (a.to_string(), Some(b.to_string())))
-                // No version token, convert to String, None
-                .unwrap_or_else(|| (c.to_string(), None))
-        })
-        .collect()
-}
 
-pub fn show(l3repo: &L3Repo, action_data: &Action) -> Result<()> {
-    if action_data.get_sections().is_empty()
-        && action_data.get_groups().is_empty()
-        && action_data.get_components().is_empty()
-    {
-        println!("Package sections:");
-        for section_id in l3repo.sections() {
-            println!("\t{}", section_id);
+    pub fn get_target_hw(&self) -> Option<&str> {
+        match self {
+            Action::Show { target_hw, .. } => target_hw.as_deref(),
+            Action::Fetch { target_hw, .. } => target_hw.as_deref(),
+            _ => None,
         }
+    }
 
-        println!("Package groups:");
-        for group_id in l3repo.groups() {
-            let group = l3repo
-                .get_group(&group_id)
-                .ok_or_else(|| Error::InvalidGroup(group_id.to_string()))?;
-            let mut versions: Vec<String> =
-                group.versions.iter().map(|v| v.version.clone()).collect();
-            versions.sort();
-            versions.dedup();
-            println!("\t{}[{}]", group_id, versions.join(", "));
+    pub fn sort(&self) -> ComponentSort {
+        match self {
+            Action::Show { sort, .. } => *sort,
+            _ => ComponentSort::Id,
         }
+    }
 
-        println!("Package components:");
-        for component_id in l3repo.components() {
-            let component = l3repo
-                .get_component(&component_id)
-                .ok_or_else(|| Error::InvalidComponent(component_id.to_string()))?;
-            let mut versions: Vec<String> = component
-                .versions
-                .iter()
-                .map(|v| v.version.clone())
-                .collect();
-            versions.sort();
-            versions.dedup();
-            println!("\t{}[{}]", component_id, versions.join(", "));
+    pub fn get_version(&self) -> Option<&str> {
+        match self {
+            Action::Show { version, .. } => version.as_deref(),
+            Action::Fetch { version, .. } => version.as_deref(),
+            Action::Verify { version, .. } => version.as_deref(),
+            _ => None,
         }
     }
 
-    for section_id in action_data.get_sections() {
-        let section = l3repo
-            .get_section(section_id)
-            .ok_or_else(|| Error::InvalidSection(section_id.to_string()))?;
-        println!(
-            "Section {}: {}[{}]",
-            section.id, section.title, section.name
-        );
-        for group_id in &section.groups {
-            println!("\tChild group: {}", group_id);
+    pub fn tree(&self) -> bool {
+        match self {
+            Action::Show { tree, .. } => *tree,
+            _ => false,
         }
     }
 
-    for group_id in action_data.get_groups() {
-        let group = l3repo
-            .get_group(group_id)
-            .ok_or_else(|| Error::InvalidGroup(group_id.to_string()))?;
-        println!("Group {}: {}[{}]", group.id, group.name, group.installed_on);
-        println!("\tDescription: {}", group.description);
-        for version in &group.versions {
-            println!("\tVersion {} components:", version.version);
-            for component in &version.components {
-                println!("\t\t{}", component.id);
-            }
+    pub fn get_filter_type(&self) -> Option<&str> {
+        match self {
+            Action::Show { filter_type, .. } => filter_type.as_deref(),
+            _ => None,
         }
     }
 
-    for component_id in action_data.get_components() {
-        let component = l3repo
-            .get_component(component_id)
-            .ok_or_else(|| Error::InvalidComponent(component_id.to_string()))?;
-        println!(
-            "Component {}: {}[{}]",
-            component.id, component.name, component.comp_type
-        );
-        println!("\tDescription: {}", component.description);
-        for version in &component.versions {
-            println!("\tVersion {}:", version.version);
-            println!("\t\tInstall size: {} MB", version.install_size_mb);
-            for os in &version.operating_systems {
-                println!("\t\tSupported OS: {}", os);
-            }
-            for target_id in &version.target_ids {
-                println!("\t\tSupported HW: {}", target_id);
-            }
-            for file in &version.download_files {
-                println!("\t\tPackage {}", file.file_name)
-            }
+    /// --min-free-space in bytes
+    pub fn min_free_space(&self) -> Option<u64> {
+        match self {
+            Action::Fetch { min_free_space, .. } => min_free_space.map(|mb| mb * 1024 * 1024),
+            _ => None,
         }
     }
-    Ok(())
-}
 
-pub fn fetch(l3repo: &L3Repo, action_data: &Action, cache_dir: &Path) -> Result<()> {
-    debug!(
-        "Creating cache directory {} (if it doesn't already exist)",
-        cache_dir.to_string_lossy().to_string()
-    );
-    std::fs::create_dir_all(cache_dir).map_err(Error::from)?;
-    for (component_id, opt_ver) in get_component_ids(l3repo, action_data) {
-        let component = l3repo
-            .get_component(&component_id)
-            .ok_or_else(|| Error::InvalidComponent(component_id.to_string()))?;
-        if opt_ver.is_none() && !component.versions.is_empty() {
-            warn!(
-                "No version specified for component {}. Using first available.",
-                component_id
-            );
+    pub fn force(&self) -> bool {
+        match self {
+            Action::Verify { force, .. } => *force,
+            _ => false,
         }
-        for component_ver in opt_ver
-            .map(|ver| {
-                component
-                    .versions
-                    .iter()
-                    .filter(|&c_ver| c_ver.version == ver)
-                    .map(|c_ver| (*c_ver).clone())
-                    .collect()
-            })
-            .unwrap_or_else(|| component.versions.clone())
-        {
-            for file in &component_ver.download_files {
-                let local_filename = cache_dir.join(file.file_name.clone());
-                let remote_file_url = l3repo
-                    .source
-                    .as_ref()
-                    .expect("Source not set on l3 repo!")
-                    .join(&file.url)
-                    .map_err(Error::from)?;
-                info!(
-                    "Retrieving {} package {} into {}...",
-                    component_id,
-                    file.file_name,
-                    cache_dir.display()
-                );
-                let cached_file = cache::cached_get_path(remote_file_url.as_str())?;
-                if local_filename.exists() {
-                    std::fs::remove_file(&local_filename).map_err(Error::from)?;
-                }
-                fs::symlink(&cached_file, &local_filename).map_err(Error::from)?;
-            }
+    }
+
+    pub fn get_os(&self) -> Option<&str> {
+        match self {
+            Action::Fetch { os, .. } => os.as_deref(),
+            Action::Verify { os, .. } => os.as_deref(),
+            _ => None,
         }
     }
-    Ok(())
-}
 
-fn validate_file(filename: &Path, checksum_type: &str, checksum: &str) -> Result<()> {
-    if !filename.exists() {
-        return Err(Error::FileNotExist(filename.to_string_lossy().to_string()));
+    fn has_selection(&self) -> bool {
+        !self.get_sections().is_empty()
+            || !self.get_groups().is_empty()
+            || !self.get_components().is_empty()
     }
 
-    info!("Verifying file checksum...");
-    let file_meta = std::fs::metadata(filename)?;
-    let mut in_file = std::io::BufReader::new(std::fs::File::open(filename).map_err(Error::from)?);
-    match checksum_type {
-        "md5" => {
-            let mut hasher = md5::Context::new();
-            let pbar = indicatif::ProgressBar::new(file_meta.len());
-            while !in_file.fill_buf().map_err(Error::from)?.is_empty() {
-                let buf_len = in_file.buffer().len();
-                debug!("Updating checksum from {} bytes...", buf_len);
-                hasher.consume(in_file.buffer());
-                in_file.consume(buf_len);
-                pbar.inc(buf_len.try_into().unwrap());
-            }
-            let digest = hasher.compute();
-            let digest_str = format!("{:x}", digest);
-            if digest_str != checksum {
-                return Err(Error::FileDigestInvalid {
-                    file: filename.to_string_lossy().to_string(),
-                    cktype: checksum_type.to_string(),
-                    expected: checksum.to_string(),
-                    actual: digest_str,
-                });
-            }
-        }
-        _ => return Err(Error::UnsupportedChecksumType(checksum_type.to_owned())),
+    pub fn copy_files(&self) -> bool {
+        match self {
+            Action::Fetch { copy, .. } => *copy,
+            _ => false,
+        }
     }
-    Ok(())
-}
 
-pub fn verify(l3repo: &L3Repo, action_data: &Action, cache_dir: &Path) -> Result<()> {
-    for (component_id, opt_ver) in get_component_ids(l3repo, action_data) {
-        let component = l3repo
-            .get_component(&component_id)
-            .ok_or_else(|| Error::InvalidComponent(component_id.clone()))?;
+    pub fn flatten_symlinks(&self) -> bool {
+        match self {
+            Action::Fetch {
+                flatten_symlinks, ..
+            } => *flatten_symlinks,
+            _ => false,
+        }
+    }
 
-        if opt_ver.is_none() && !component.versions.is_empty() {
-            warn!(
-                "No version specified for component {}. Validating all available versions.",
-                component_id
-            );
+    pub fn keep_going(&self) -> bool {
+        match self {
+            Action::Fetch { keep_going, .. } => *keep_going,
+            Action::Verify { keep_going, .. } => *keep_going,
+            _ => false,
         }
-        for version in opt_ver
-            .map(|ver| {
-                component
-                    .versions
-                    .iter()
-                    .filter(|&c_ver| c_ver.version == ver)
-                    .map(|cmp| (*cmp).clone())
-                    .collect()
-            })
-            .unwrap_or_else(|| component.versions.clone())
-        {
-            for file in &version.download_files {
-                let local_filename = cache_dir.join(file.file_name.clone());
-                if let Err(e) = validate_file(&local_filename, &file.checksum_type, &file.checksum)
-                {
-                    match e {
-                        Error::FileDigestInvalid {
-                            file: f,
-                            cktype: ct,
-                            expected: c,
-                            actual: d,
-                        } => error!("INVALID DIGEST: {}[{}] {} != {}", f, ct, d, c),
-                        Error::FileNotExist(f) => error!("MISSING FILE:   {} does not exist", f),
-                        _ => return Err(e),
-                    }
-                } else {
-                    info!("VALID:   {}", local_filename.to_string_lossy().to_string());
-                }
-            }
+    }
+
+    pub fn dedup(&self) -> bool {
+        match self {
+            Action::Fetch { dedup, .. } => *dedup,
+            _ => false,
         }
     }
-    Ok(())
+
+    pub fn only_missing(&self) -> bool {
+        match self {
+            Action::Fetch { only_missing, .. } => *only_missing,
+            _ => false,
+        }
+    }
+
+    pub fn skip_if_unchanged(&self) -> bool {
+        match self {
+            Action::Fetch {
+                skip_if_unchanged, ..
+            } => *skip_if_unchanged,
+            _ => false,
+        }
+    }
+
+    pub fn repair(&self) -> bool {
+        match self {
+            Action::Verify { repair, .. } => *repair,
+            _ => false,
+        }
+    }
+
+    pub fn checksum_only(&self) -> bool {
+        match self {
+            Action::Verify { checksum_only, .. } => *checksum_only,
+            _ => false,
+        }
+    }
+
+    pub fn explain(&self) -> bool {
+        match self {
+            Action::Fetch { explain, .. } => *explain,
+            Action::Verify { explain, .. } => *explain,
+            _ => false,
+        }
+    }
+
+    pub fn components_json(&self) -> bool {
+        match self {
+            Action::Fetch {
+                components_json, ..
+            } => *components_json,
+            _ => false,
+        }
+    }
+
+    pub fn strict(&self) -> bool {
+        match self {
+            Action::Verify { strict, .. } => *strict,
+            _ => false,
+        }
+    }
+
+    pub fn size_only(&self) -> bool {
+        match self {
+            Action::Verify { size_only, .. } => *size_only,
+            _ => false,
+        }
+    }
+
+    pub fn layout(&self) -> Layout {
+        match self {
+            Action::Fetch { layout, .. } => *layout,
+            Action::Verify { layout, .. } => *layout,
+            _ => Layout::Flat,
+        }
+    }
+
+    pub fn get_lockfile(&self) -> Option<&Path> {
+        match self {
+            Action::Fetch { lockfile, .. } => lockfile.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn locked(&self) -> bool {
+        match self {
+            Action::Fetch { locked, .. } => *locked,
+            _ => false,
+        }
+    }
+
+    pub fn get_output_dir(&self) -> Option<&Path> {
+        match self {
+            Action::Fetch { output_dir, .. } => output_dir.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+fn get_selectable_component<'a>(
+    l3repo: &'a L3Repo,
+    id: &str,
+    include_hidden: bool,
+) -> Result<&'a L3Component> {
+    let component = l3repo
+        .get_component(id)
+        .ok_or_else(|| Error::InvalidComponent(id.to_string()))?;
+    if !component.is_visible && !include_hidden {
+        return Err(Error::HiddenComponent(id.to_string()));
+    }
+    Ok(component)
+}
+
+/// Split a "<name>:<version>" selection into its name and optional version
+fn split_version(spec: &str) -> (String, Option<String>) {
+    match spec.find(':') {
+        Some(off) => (spec[..off].to_string(), Some(spec[off + 1..].to_string())),
+        None => (spec.to_string(), None),
+    }
+}
+
+/// Selected components and their requested versions, sorted by id so they
+/// are always processed in the same order
+fn get_component_ids(l3repo: &L3Repo, action_data: &Action) -> BTreeSet<(String, Option<String>)> {
+    let mut component_ids: BTreeSet<String> = action_data
+        .get_components()
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    for section in action_data.get_sections() {
+        component_ids.extend(l3repo.get_components_for_section(section));
+    }
+    for group in action_data.get_groups() {
+        let (group_id, group_ver) = split_version(group);
+        component_ids.extend(l3repo.get_components_for_group(&group_id, group_ver.as_deref()));
+    }
+    if !action_data.has_selection() && action_data.get_target_hw().is_some() {
+        component_ids.extend(if action_data.include_hidden() {
+            l3repo.components()
+        } else {
+            l3repo.visible_components()
+        });
+    }
+    // --version covers the components that don't name their own
+    component_ids
+        .iter()
+        .map(|c| match split_version(c) {
+            (id, None) => (id, action_data.get_version().map(str::to_string)),
+            selection => selection,
+        })
+        .collect()
+}
+
+fn no_matching_version(component: &L3Component, version: &str) -> Error {
+    Error::NoMatchingVersion {
+        component: component.id.clone(),
+        version: version.to_string(),
+        available: component
+            .versions
+            .iter()
+            .map(|v| v.version.clone())
+            .collect(),
+    }
+}
+
+/// The version's download files for the --os selection, warning when the
+/// version has OS specific variants but no OS was chosen
+fn os_download_files<'a>(
+    action_data: &Action,
+    component_id: &str,
+    version: &'a L3ComponentVersion,
+) -> Vec<&'a L3ComponentVersionDownloadFile> {
+    if action_data.get_os().is_none() && version.has_os_variants() {
+        warn!(
+            "Component {} version {} has packages for several operating systems ({}), \
+             including all of them. Use --os to choose one.",
+            component_id,
+            version.version,
+            version.operating_systems.join(", ")
+        );
+    }
+    version.download_files_for_os(action_data.get_os())
+}
+
+/// Whether a component version passes the --target-hw filter
+fn matches_target_hw(action_data: &Action, version: &L3ComponentVersion) -> bool {
+    action_data
+        .get_target_hw()
+        .is_none_or(|hw| version.supports_target_hw(hw))
+}
+
+fn format_size_mb(size_mb: f64) -> String {
+    if size_mb >= 1024.0 {
+        format!("{:.1} GB", size_mb / 1024.0)
+    } else {
+        format!("{:.1} MB", size_mb)
+    }
+}
+
+/// Provenance of the release the output was generated from
+#[derive(Serialize)]
+struct ReleaseSummary<'a> {
+    product_category: &'a str,
+    target_os: &'a str,
+    title: &'a str,
+    version: &'a str,
+    build: &'a str,
+    revision: u8,
+    target_hw: &'a [String],
+}
+
+impl<'a> From<&'a L2Release> for ReleaseSummary<'a> {
+    fn from(release: &'a L2Release) -> Self {
+        Self {
+            product_category: &release.product_category,
+            target_os: &release.target_os,
+            title: &release.title,
+            version: &release.release_version,
+            build: &release.release_build,
+            revision: release.release_revision,
+            target_hw: &release.target_hw,
+        }
+    }
+}
+
+fn print_release_summary(release: &L2Release) {
+    println!("Release {}", release.title);
+    println!(
+        "\tVersion {} build {} revision {}",
+        release.release_version, release.release_build, release.release_revision
+    );
+    for target_hw in &release.target_hw {
+        println!("\tSupported HW: {}", target_hw);
+    }
+}
+
+#[derive(Serialize)]
+struct ShowSelection<'a> {
+    release: ReleaseSummary<'a>,
+    sections: Vec<&'a L3Section>,
+    groups: Vec<Cow<'a, L3Group>>,
+    components: Vec<Cow<'a, L3Component>>,
+}
+
+/// The versions of `component` show lists: the one `version`, or else
+/// --version, selects, or all of them, keeping those for --target-hw
+fn shown_versions<'a>(
+    component: &'a L3Component,
+    version: Option<&str>,
+    action_data: &Action,
+) -> Result<Vec<&'a L3ComponentVersion>> {
+    let versions = match version.or_else(|| action_data.get_version()) {
+        Some(version) => vec![component
+            .selected_version(Some(version))
+            .ok_or_else(|| no_matching_version(component, version))?],
+        None => component.versions.iter().collect(),
+    };
+    Ok(versions
+        .into_iter()
+        .filter(|v| matches_target_hw(action_data, v))
+        .collect())
+}
+
+/// The component a `COMP[:version]` spec names, with only the versions
+/// show lists for it
+fn shown_component<'a>(
+    l3repo: &'a L3Repo,
+    spec: &str,
+    action_data: &Action,
+) -> Result<Cow<'a, L3Component>> {
+    let (id, version) = split_version(spec);
+    let component = get_selectable_component(l3repo, &id, action_data.include_hidden())?;
+    let versions = shown_versions(component, version.as_deref(), action_data)?;
+    if versions.len() == component.versions.len() {
+        return Ok(Cow::Borrowed(component));
+    }
+    Ok(Cow::Owned(L3Component {
+        versions: versions.into_iter().cloned().collect(),
+        ..component.clone()
+    }))
+}
+
+/// The group a `GROUP[:version]` spec names, with only that version when
+/// one is given
+fn shown_group<'a>(l3repo: &'a L3Repo, spec: &str) -> Result<Cow<'a, L3Group>> {
+    let (id, version) = split_version(spec);
+    let group = l3repo
+        .get_group(&id)
+        .ok_or_else(|| Error::InvalidGroup(id.to_string()))?;
+    let version = match version {
+        Some(version) => version,
+        None => return Ok(Cow::Borrowed(group)),
+    };
+    let group_version = group
+        .versions
+        .iter()
+        .find(|v| v.version == version)
+        .ok_or_else(|| Error::InvalidGroup(spec.to_string()))?;
+    Ok(Cow::Owned(L3Group {
+        versions: vec![group_version.clone()],
+        ..group.clone()
+    }))
+}
+
+/// Components of the full show listing, filtered and ordered by the show
+/// options
+fn listed_components<'a>(l3repo: &'a L3Repo, action_data: &Action) -> Vec<&'a L3Component> {
+    let include_hidden = action_data.include_hidden();
+    let mut components: Vec<&L3Component> = l3repo
+        .components()
+        .iter()
+        .filter_map(|id| l3repo.get_component(id))
+        .filter(|c| include_hidden || c.is_visible)
+        .filter(|c| {
+            action_data
+                .get_target_hw()
+                .is_none_or(|hw| c.supports_target_hw(hw))
+        })
+        .filter(|c| {
+            action_data
+                .get_filter_type()
+                .is_none_or(|comp_type| c.comp_type == comp_type)
+        })
+        .collect();
+    match action_data.sort() {
+        // components() is already sorted by id
+        ComponentSort::Id => {}
+        ComponentSort::Name => components.sort_by(|a, b| a.name.cmp(&b.name)),
+        ComponentSort::Size => {
+            let size = |c: &L3Component| c.versions.first().map_or(0.0, |v| v.install_size_mb);
+            components.sort_by(|a, b| {
+                size(b)
+                    .partial_cmp(&size(a))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+        }
+    }
+    components
+}
+
+fn get_show_selection<'a>(l3repo: &'a L3Repo, action_data: &Action) -> Result<ShowSelection<'a>> {
+    if action_data.get_sections().is_empty()
+        && action_data.get_groups().is_empty()
+        && action_data.get_components().is_empty()
+    {
+        let include_hidden = action_data.include_hidden();
+        // Sections keep the repo's order, the rest come from maps and are
+        // sorted so the output is stable
+        return Ok(ShowSelection {
+            release: (&l3repo.information.release).into(),
+            sections: l3repo
+                .sections
+                .iter()
+                .filter(|s| include_hidden || s.is_displayed())
+                .collect(),
+            groups: l3repo
+                .groups()
+                .iter()
+                .filter_map(|id| l3repo.get_group(id))
+                .map(Cow::Borrowed)
+                .collect(),
+            components: listed_components(l3repo, action_data)
+                .into_iter()
+                .map(Cow::Borrowed)
+                .collect(),
+        });
+    }
+
+    let sections = action_data
+        .get_sections()
+        .iter()
+        .map(|id| {
+            l3repo
+                .get_section(id)
+                .ok_or_else(|| Error::InvalidSection(id.to_string()))
+        })
+        .collect::<Result<_>>()?;
+    let groups = action_data
+        .get_groups()
+        .iter()
+        .map(|spec| shown_group(l3repo, spec))
+        .collect::<Result<_>>()?;
+    let components = action_data
+        .get_components()
+        .iter()
+        .map(|spec| shown_component(l3repo, spec, action_data))
+        .collect::<Result<_>>()?;
+    Ok(ShowSelection {
+        release: (&l3repo.information.release).into(),
+        sections,
+        groups,
+        components,
+    })
+}
+
+/// Print the dependencies of `version` below `prefix`, like cargo tree.
+/// `path` holds the ids from the root down, so a dependency back onto one
+/// of them is marked as a cycle instead of being followed.
+fn print_dependency_tree(
+    l3repo: &L3Repo,
+    version: &L3ComponentVersion,
+    prefix: &str,
+    path: &mut Vec<String>,
+) {
+    let ids = version.dependency_ids();
+    for (i, id) in ids.iter().enumerate() {
+        let last = i + 1 == ids.len();
+        let branch = if last { "└── " } else { "├── " };
+        let dependency = match l3repo.get_component(id) {
+            Some(dependency) => dependency,
+            None => {
+                println!("{}{}{} (not in the repo)", prefix, branch, id);
+                continue;
+            }
+        };
+        if path.contains(id) {
+            println!("{}{}{} ({}) (cycle)", prefix, branch, id, dependency.name);
+            continue;
+        }
+        println!("{}{}{} ({})", prefix, branch, id, dependency.name);
+        // Dependencies don't name a version, so follow the default one
+        if let Some(dependency_version) = dependency.selected_version(None) {
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            path.push(id.clone());
+            print_dependency_tree(l3repo, dependency_version, &prefix, path);
+            path.pop();
+        }
+    }
+}
+
+pub fn show(l3repo: &L3Repo, action_data: &Action, format: OutputFormat) -> Result<()> {
+    if format != OutputFormat::Text {
+        return format.print(&get_show_selection(l3repo, action_data)?);
+    }
+
+    print_release_summary(&l3repo.information.release);
+
+    if action_data.get_sections().is_empty()
+        && action_data.get_groups().is_empty()
+        && action_data.get_components().is_empty()
+    {
+        let section_ids = if action_data.include_hidden() {
+            l3repo.sections()
+        } else {
+            l3repo.displayed_sections()
+        };
+
+        println!("Package sections:");
+        for section_id in section_ids {
+            let section = l3repo
+                .get_section(&section_id)
+                .ok_or_else(|| Error::InvalidSection(section_id.to_string()))?;
+            if section.is_selectable() {
+                println!("\t{}", section_id);
+            } else {
+                println!("\t{} (not selectable)", section_id);
+            }
+        }
+
+        println!("Package groups:");
+        for group_id in l3repo.groups() {
+            let group = l3repo
+                .get_group(&group_id)
+                .ok_or_else(|| Error::InvalidGroup(group_id.to_string()))?;
+            let mut versions: Vec<String> =
+                group.versions.iter().map(|v| v.version.clone()).collect();
+            versions.sort();
+            versions.dedup();
+            println!("\t{}[{}]", group_id, versions.join(", "));
+        }
+
+        println!("Package components:");
+        for component in listed_components(l3repo, action_data) {
+            let mut versions: Vec<String> = component
+                .versions
+                .iter()
+                .map(|v| v.version.clone())
+                .collect();
+            versions.sort();
+            versions.dedup();
+            println!("\t{}[{}]", component.id, versions.join(", "));
+        }
+    }
+
+    for section_id in action_data.get_sections() {
+        let section = l3repo
+            .get_section(section_id)
+            .ok_or_else(|| Error::InvalidSection(section_id.to_string()))?;
+        println!(
+            "Section {}: {}[{}]",
+            section.id, section.title, section.name
+        );
+        println!(
+            "\tInstall size: {}",
+            format_size_mb(l3repo.get_section_install_size_mb(section_id))
+        );
+        for group_id in &section.groups {
+            println!("\tChild group: {}", group_id);
+        }
+    }
+
+    for group_spec in action_data.get_groups() {
+        let group = shown_group(l3repo, group_spec)?;
+        println!("Group {}: {}[{}]", group.id, group.name, group.installed_on);
+        println!("\tDescription: {}", group.description);
+        for version in &group.versions {
+            println!(
+                "\tVersion {} install size: {}",
+                version.version,
+                format_size_mb(l3repo.install_size_mb(&version.components))
+            );
+            println!("\tVersion {} components:", version.version);
+            for component in &version.components {
+                println!("\t\t{}", component.id);
+            }
+        }
+    }
+
+    if action_data.tree() {
+        for component_id in action_data.get_components() {
+            let (id, version) = split_version(component_id);
+            let version = version.as_deref().or_else(|| action_data.get_version());
+            let component = get_selectable_component(l3repo, &id, action_data.include_hidden())?;
+            println!("{} ({})", component.id, component.name);
+            if let Some(version) = component.selected_version(version) {
+                print_dependency_tree(l3repo, version, "", &mut vec![component.id.clone()]);
+            }
+        }
+        return Ok(());
+    }
+
+    for component_id in action_data.get_components() {
+        let (id, version) = split_version(component_id);
+        let component = get_selectable_component(l3repo, &id, action_data.include_hidden())?;
+        println!(
+            "Component {}: {}[{}]",
+            component.id, component.name, component.comp_type
+        );
+        println!("\tDescription: {}", component.description);
+        for version in shown_versions(component, version.as_deref(), action_data)? {
+            println!("\tVersion {}:", version.version);
+            println!("\t\tInstall size: {} MB", version.install_size_mb);
+            for os in &version.operating_systems {
+                println!("\t\tSupported OS: {}", os);
+            }
+            for target_id in &version.target_ids {
+                println!("\t\tSupported HW: {}", target_id);
+            }
+            for file in &version.download_files {
+                println!("\t\tPackage {}", file.file_name);
+                let install = &file.install_parameters;
+                println!("\t\t\tInstall type: {}", install.install_type);
+                if let Some(apt_switch) = &install.additional_parameters.apt_switch {
+                    println!("\t\t\tApt switch: {}", apt_switch);
+                }
+                let apt_packages = install.additional_parameters.apt_package_names();
+                if !apt_packages.is_empty() {
+                    println!("\t\t\tApt packages: {}", apt_packages.join(", "));
+                }
+                let post_uninstall = install.additional_parameters.post_uninstall_command_list();
+                if !post_uninstall.is_empty() {
+                    println!("\t\t\tPost-uninstall commands:");
+                    for command in post_uninstall {
+                        println!("\t\t\t\t{}", command);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn doctor(config: &SdkmConfig) -> Result<()> {
+    let (mut passed, mut failed) = (0, 0);
+    let mut check = |name: &str, result: std::result::Result<String, String>| match result {
+        Ok(detail) => {
+            println!("PASS  {}: {}", name, detail);
+            passed += 1;
+        }
+        Err(e) => {
+            println!("FAIL  {}: {}", name, e);
+            failed += 1;
+        }
+    };
+
+    check(
+        "Main repo reachable",
+        cache::check_reachable(config.main_repo_url.as_str())
+            .map(|latency| format!("{} in {} ms", config.main_repo_url, latency.as_millis()))
+            .map_err(|e| e.to_string()),
+    );
+
+    let cache_dir = cache::get_cache_dir(None);
+    check(
+        "Cache directory writable",
+        std::fs::create_dir_all(&cache_dir)
+            .and_then(|_| {
+                let probe = cache_dir.join(".doctor");
+                std::fs::write(&probe, b"")?;
+                std::fs::remove_file(&probe)
+            })
+            .map(|_| cache_dir.display().to_string())
+            .map_err(|e| e.to_string()),
+    );
+
+    check(
+        "Cache size",
+        cache::dir_size(&cache_dir)
+            .map(|size| indicatif::HumanBytes(size).to_string())
+            .map_err(|e| e.to_string()),
+    );
+
+    let http_cache = cache::get_cache_dir(Some(Path::new("http_cache")));
+    check(
+        "Cache entries complete",
+        match caching_client::incomplete_entries(&http_cache) {
+            Ok(incomplete) if incomplete.is_empty() => Ok("no incomplete entries".to_string()),
+            Ok(incomplete) => {
+                for entry in &incomplete {
+                    warn!("Incomplete cache entry {}", entry.display());
+                }
+                Err(format!(
+                    "{} entries missing data or metadata, such as {}",
+                    incomplete.len(),
+                    incomplete[0].display()
+                ))
+            }
+            Err(e) => Err(e.to_string()),
+        },
+    );
+
+    check(
+        "Cache metadata readable",
+        match caching_client::corrupt_entries(&http_cache) {
+            Ok(corrupt) if corrupt.is_empty() => Ok("no corrupt entries".to_string()),
+            Ok(corrupt) => {
+                for entry in &corrupt {
+                    warn!("Corrupt cache entry {}", entry.display());
+                }
+                Err(format!(
+                    "{} entries with unreadable metadata, such as {} (clean --corrupt removes them)",
+                    corrupt.len(),
+                    corrupt[0].display()
+                ))
+            }
+            Err(e) => Err(e.to_string()),
+        },
+    );
+
+    println!("doctor: {} passed, {} failed", passed, failed);
+    if failed > 0 {
+        return Err(Error::ChecksFailed { passed, failed });
+    }
+    Ok(())
+}
+
+/// What this build supports, for scripts to check before relying on it
+#[derive(Serialize)]
+struct Capabilities {
+    checksum_types: Vec<String>,
+    output_formats: Vec<String>,
+    compression: Vec<String>,
+}
+
+pub fn capabilities(format: OutputFormat) -> Result<()> {
+    let capabilities = Capabilities {
+        checksum_types: checksum::supported_types(),
+        output_formats: OutputFormat::variants(),
+        compression: caching_client::Compression::variants(),
+    };
+    match format {
+        OutputFormat::Text => OutputFormat::Json.print(&capabilities),
+        _ => format.print(&capabilities),
+    }
+}
+
+/// Remove the http cache entries missing their data or metadata, and with
+/// `corrupt` those whose metadata can't be read
+pub fn clean(corrupt: bool) -> Result<()> {
+    let http_cache = cache::get_cache_dir(Some(Path::new("http_cache")));
+    let mut broken = caching_client::incomplete_entries(&http_cache)?;
+    if corrupt {
+        broken.extend(caching_client::corrupt_entries(&http_cache)?);
+    }
+    for entry in &broken {
+        info!("Removing cache entry {}", entry.display());
+        std::fs::remove_dir_all(entry)?;
+    }
+    println!("clean: removed {} cache entries", broken.len());
+    Ok(())
+}
+
+/// Download the release notes of the release into `cache_dir`, or just point
+/// at them if the repo doesn't offer them for download
+pub fn notes(l3repo: &L3Repo, cache_dir: &Path) -> Result<()> {
+    let release = &l3repo.information.release;
+    let release_notes = &release.release_notes;
+    let url = match &l3repo.source {
+        Some(source) => source.join(&release_notes.release_notes_url)?,
+        None => url::Url::parse(&release_notes.release_notes_url)?,
+    };
+    if !release_notes.release_notes_download {
+        warn!(
+            "The repo doesn't offer the release notes of {} for download",
+            release.title
+        );
+        println!("{}", url);
+        return Ok(());
+    }
+    let file_name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("release_notes");
+    let local_filename = cache_dir.join(safe_path_component(file_name)?);
+    info!(
+        "Retrieving {} into {}...",
+        release_notes.release_notes_title,
+        local_filename.display()
+    );
+    let cached = cache::cached_get_document(url.as_str())?;
+    caching_client::copy_data(&cached.path, &local_filename)?;
+    cache::apply_cache_mode(&local_filename)?;
+    println!("{}", local_filename.display());
+    Ok(())
+}
+
+/// Quote `s` for a POSIX shell
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Print every package of the repo, with its url, file name, size, and
+/// checksum, in the input format of an external downloader
+pub fn export(l3repo: &L3Repo, format: ExportFormat) -> Result<()> {
+    let files = l3repo.all_download_files()?;
+    if format == ExportFormat::Shell {
+        println!("#!/bin/sh");
+        println!("# {}", l3repo.information.release.title);
+        println!("set -e");
+    }
+    for (component_id, file, url) in files {
+        let file_name = safe_path_component(&file.file_name)?;
+        match format {
+            ExportFormat::Aria2 => {
+                println!("# {}, {} bytes", component_id, file.size);
+                println!("{}", url);
+                println!("  out={}", file_name);
+                // aria2c names the digests it checks differently
+                match file.checksum_type.as_str() {
+                    "md5" => println!("  checksum=md5={}", file.checksum),
+                    "sha1" => println!("  checksum=sha-1={}", file.checksum),
+                    "sha256" => println!("  checksum=sha-256={}", file.checksum),
+                    _ => {}
+                }
+            }
+            ExportFormat::Shell => {
+                println!();
+                println!("# {}, {} bytes", component_id, file.size);
+                println!(
+                    "curl -fL -o {} {}",
+                    shell_quote(file_name),
+                    shell_quote(url.as_str())
+                );
+                match file.checksum_type.as_str() {
+                    "md5" | "sha1" | "sha256" | "sha512" => println!(
+                        "echo {} | {}sum -c -",
+                        shell_quote(&format!("{}  {}", file.checksum, file_name)),
+                        file.checksum_type
+                    ),
+                    _ => println!(
+                        "# {} checksum {} is not checked",
+                        file.checksum_type, file.checksum
+                    ),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn list(
+    config: &SdkmConfig,
+    what: ListKind,
+    product_category: Option<&str>,
+    target_os: Option<&str>,
+    format: OutputFormat,
+) -> Result<()> {
+    let l1repo = L1Repo::fetch(config.main_repo_url.as_str())?;
+    let values = if what == ListKind::Categories {
+        l1repo.product_categories()
+    } else {
+        let req_product_category = product_category
+            .ok_or_else(|| Error::MissingProductCategory(l1repo.product_categories()))?;
+        let category = l1repo
+            .get_product_category(req_product_category)
+            .ok_or_else(|| {
+                Error::InvalidProductCategory(
+                    req_product_category.to_string(),
+                    l1repo.product_categories(),
+                )
+            })?;
+        if what == ListKind::Targets {
+            category.product_lines()
+        } else {
+            let req_target_os =
+                target_os.ok_or_else(|| Error::MissingTargetOS(category.product_lines()))?;
+            let l2_rel_url = l1repo.get_product_url(req_product_category, req_target_os)?;
+            L2Repo::fetch(l2_rel_url.as_str())?.releases()
+        }
+    };
+
+    if format != OutputFormat::Text {
+        return format.print(&values);
+    }
+    for value in values {
+        println!("{}", value);
+    }
+    Ok(())
+}
+
+pub fn search(l3repo: &L3Repo, query: &str) -> Result<()> {
+    let query = query.to_lowercase();
+    let is_match = |text: &str| text.to_lowercase().contains(&query);
+
+    println!("Matching sections:");
+    for section in &l3repo.sections {
+        if is_match(&section.id) || is_match(&section.name) || is_match(&section.title) {
+            println!("\t{}: {}", section.id, section.title);
+        }
+    }
+
+    println!("Matching groups:");
+    let mut groups: Vec<_> = l3repo
+        .groups
+        .values()
+        .filter(|g| is_match(&g.id) || is_match(&g.name) || is_match(&g.description))
+        .collect();
+    groups.sort_by(|a, b| a.id.cmp(&b.id));
+    for group in groups {
+        println!("\t{}: {}", group.id, group.name);
+    }
+
+    println!("Matching components:");
+    let mut components: Vec<_> = l3repo
+        .components
+        .values()
+        .filter(|c| is_match(&c.id) || is_match(&c.name) || is_match(&c.description))
+        .collect();
+    components.sort_by(|a, b| a.id.cmp(&b.id));
+    for component in components {
+        println!("\t{}: {}", component.id, component.name);
+    }
+    Ok(())
+}
+
+/// Tally of per-package results for actions that honor --keep-going
+struct Outcomes {
+    keep_going: bool,
+    succeeded: usize,
+    failed: usize,
+}
+
+impl Outcomes {
+    fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            succeeded: 0,
+            failed: 0,
+        }
+    }
+
+    /// Count a result, handing back the error unless we're keeping going
+    fn record(&mut self, result: Result<()>) -> Result<()> {
+        match result {
+            Ok(()) => {
+                self.succeeded += 1;
+                Ok(())
+            }
+            Err(_) if self.keep_going => {
+                self.failed += 1;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn finish(self, action: &str) -> Result<()> {
+        info!(
+            "{}: {} succeeded, {} failed",
+            action, self.succeeded, self.failed
+        );
+        if self.failed > 0 {
+            return Err(Error::PackagesFailed {
+                action: action.to_string(),
+                succeeded: self.succeeded,
+                failed: self.failed,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Name of the manifest written into the fetch output directory
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Record of a fetch: which release the packages came from and what was
+/// retrieved
+#[derive(Serialize)]
+struct FetchManifest<'a> {
+    release: ReleaseSummary<'a>,
+    packages: Vec<ManifestEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry<'a> {
+    component: String,
+    version: String,
+    file_name: &'a str,
+    url: String,
+    size: u32,
+    checksum: &'a str,
+    checksum_type: &'a str,
+}
+
+/// File in the per-release cache dir recording the release build last
+/// fetched, for --skip-if-unchanged
+pub const LAST_SYNC_FILE: &str = "last_sync.json";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LastSync {
+    release_build: String,
+    release_revision: u8,
+    /// Records from before the selection was kept never match
+    #[serde(default)]
+    selection: Option<SyncSelection>,
+    synced_at: DateTime<offset::Utc>,
+}
+
+/// What a fetch placed where: the component versions selected, and the
+/// options deciding their packages and the paths they went to
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct SyncSelection {
+    components: Vec<String>,
+    os: Option<String>,
+    target_hw: Option<String>,
+    layout: Layout,
+    output_dir: PathBuf,
+}
+
+impl SyncSelection {
+    fn new(
+        files: &[(String, &L3ComponentVersion, &L3ComponentVersionDownloadFile)],
+        action_data: &Action,
+        output_dir: &Path,
+    ) -> Self {
+        let components = files
+            .iter()
+            .map(|(component_id, component_ver, _)| {
+                format!("{}:{}", component_id, component_ver.version)
+            })
+            .collect::<BTreeSet<_>>();
+        Self {
+            components: components.into_iter().collect(),
+            os: action_data.get_os().map(str::to_string),
+            target_hw: action_data.get_target_hw().map(str::to_string),
+            layout: action_data.layout(),
+            output_dir: output_dir
+                .canonicalize()
+                .unwrap_or_else(|_| output_dir.to_path_buf()),
+        }
+    }
+}
+
+impl LastSync {
+    fn load(cache_dir: &Path) -> Result<Self> {
+        serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(
+            cache_dir.join(LAST_SYNC_FILE),
+        )?))
+        .map_err(Error::from)
+    }
+
+    fn save(&self, cache_dir: &Path) -> Result<()> {
+        let path = cache_dir.join(LAST_SYNC_FILE);
+        debug!("Recording the synced release build in {}", path.display());
+        serde_json::to_writer_pretty(std::fs::File::create(&path)?, self)?;
+        cache::apply_cache_mode(&path)
+    }
+
+    fn matches(&self, release: &L2Release, selection: &SyncSelection) -> bool {
+        self.release_build == release.release_build
+            && self.release_revision == release.release_revision
+            && self.selection.as_ref() == Some(selection)
+    }
+}
+
+fn write_manifest(output_dir: &Path, manifest: &FetchManifest) -> Result<()> {
+    let manifest_path = output_dir.join(MANIFEST_FILE);
+    debug!("Writing fetch manifest {}", manifest_path.display());
+    let manifest_file = std::fs::File::create(&manifest_path).map_err(Error::from)?;
+    serde_json::to_writer_pretty(manifest_file, manifest).map_err(Error::from)
+}
+
+/// Size of a fetched package and whether it came from the cache
+struct Transfer {
+    bytes: u64,
+    from_cache: bool,
+}
+
+/// Tally of a fetch for the closing summary
+#[derive(Default)]
+struct FetchSummary {
+    components: HashSet<String>,
+    files: usize,
+    downloaded_bytes: u64,
+    cached_bytes: u64,
+}
+
+impl FetchSummary {
+    fn add(&mut self, component_id: &str, transfer: &Transfer) {
+        self.components.insert(component_id.to_string());
+        self.files += 1;
+        if transfer.from_cache {
+            self.cached_bytes += transfer.bytes;
+        } else {
+            self.downloaded_bytes += transfer.bytes;
+        }
+    }
+
+    fn print(&self, elapsed: std::time::Duration) {
+        println!(
+            "Fetched {} packages of {} components in {}",
+            self.files,
+            self.components.len(),
+            indicatif::HumanDuration(elapsed)
+        );
+        println!(
+            "\tDownloaded: {}",
+            indicatif::HumanBytes(self.downloaded_bytes)
+        );
+        println!("\tFrom cache: {}", indicatif::HumanBytes(self.cached_bytes));
+    }
+}
+
+fn fetch_file(
+    l3repo: &L3Repo,
+    action_data: &Action,
+    component_id: &str,
+    file: &L3ComponentVersionDownloadFile,
+    local_filename: &Path,
+) -> Result<Transfer> {
+    let remote_file_url = l3repo.download_url(file)?;
+    info!(
+        "Retrieving {} package {} into {}...",
+        component_id,
+        file.file_name,
+        local_filename.display()
+    );
+    if file.size == 0 {
+        warn!(
+            "Component {} package {} declares a size of 0 bytes",
+            component_id, file.file_name
+        );
+    }
+    // A bad file may come from a bad cache entry, so repairs skip the
+    // cache's validators and download it again
+    let credentials = l3repo
+        .information
+        .target_access_info
+        .credentials_for(&remote_file_url);
+    let cached = if action_data.repair() {
+        cache::refetch_path(remote_file_url.as_str(), credentials.as_ref())?
+    } else {
+        cache::cached_get_path(remote_file_url.as_str(), credentials.as_ref())?
+    };
+    let mut cached_file = cached.path;
+    if action_data.dedup() {
+        let digest = get_digest(&cached_file, &file.checksum_type)?;
+        cached_file = cache::dedup_cached_file(&cached_file, &file.checksum_type, &digest)?;
+    }
+    let actual_size = caching_client::data_size(&cached_file)?;
+    if file.size != 0 && actual_size != u64::from(file.size) {
+        warn!(
+            "Component {} package {} is {} bytes, but the repo declares {} bytes",
+            component_id, file.file_name, actual_size, file.size
+        );
+    }
+    if let Some(parent) = local_filename.parent() {
+        cache::create_cache_dir_all(parent)?;
+    }
+    // A link to compressed data would be useless, so it's always copied
+    // out decompressed
+    let compressed = caching_client::data_compression(&cached_file).is_some();
+    if action_data.copy_files() || compressed {
+        // Copying would write through a link left from an earlier fetch
+        clear_package_path(local_filename)?;
+        caching_client::copy_data(&cached_file, local_filename)?;
+        cache::apply_cache_mode(local_filename)?;
+    } else {
+        relink(&cached_file, local_filename)?;
+    }
+    Ok(Transfer {
+        bytes: actual_size,
+        from_cache: cached.from_cache,
+    })
+}
+
+/// Remove whatever is at `path` so a package can be placed there: a file,
+/// or a link even if it dangles.  A directory is an error rather than
+/// something to delete.
+fn clear_package_path(path: &Path) -> Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => Err(Error::from(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} is a directory, not replacing it", path.display()),
+        ))),
+        // Gone already is as good as removed
+        Ok(_) => match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::from(e)),
+            _ => Ok(()),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+/// Point a symlink at `link` to `target`, replacing whatever is there.
+/// Something else may be placed at `link` between clearing and linking,
+/// so that is retried a few times.
+fn relink(target: &Path, link: &Path) -> Result<()> {
+    const ATTEMPTS: usize = 3;
+    for attempt in 1..ATTEMPTS {
+        clear_package_path(link)?;
+        match fs::symlink(target, link) {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => debug!(
+                "{} reappeared before it was linked, attempt {} of {}",
+                link.display(),
+                attempt,
+                ATTEMPTS
+            ),
+            result => return result.map_err(Error::from),
+        }
+    }
+    clear_package_path(link)?;
+    fs::symlink(target, link).map_err(Error::from)
+}
+
+/// The package files of the lockfile with --locked, else those of the
+/// selection
+fn get_selected_files<'a>(
+    l3repo: &'a L3Repo,
+    action_data: &Action,
+    outcomes: &mut Outcomes,
+) -> Result<
+    Vec<(
+        String,
+        &'a L3ComponentVersion,
+        &'a L3ComponentVersionDownloadFile,
+    )>,
+> {
+    match action_data.get_lockfile() {
+        Some(path) if action_data.locked() => {
+            if action_data.has_selection() {
+                warn!("Fetching the packages in the lockfile, ignoring the selection");
+            }
+            Lockfile::load(path)?.resolve(l3repo)
+        }
+        _ => get_fetch_files(l3repo, action_data, outcomes),
+    }
+}
+
+/// How a package's download url was resolved, for --explain
+#[derive(Serialize)]
+struct UrlExplanation<'a> {
+    component: String,
+    version: &'a str,
+    file_name: &'a str,
+    url: &'a str,
+    base: Option<String>,
+    comp_directory: &'a str,
+    resolved: String,
+}
+
+/// Print, for each package fetch or verify would work on, the url the repo
+/// gives, what it's joined to, and the absolute url that results
+pub fn explain(l3repo: &L3Repo, action_data: &Action, format: OutputFormat) -> Result<()> {
+    let mut outcomes = Outcomes::new(action_data.keep_going());
+    let files = get_selected_files(l3repo, action_data, &mut outcomes)?;
+    let mut explanations = Vec::new();
+    for (component_id, component_ver, file) in files {
+        explanations.push(UrlExplanation {
+            component: component_id,
+            version: &component_ver.version,
+            file_name: &file.file_name,
+            url: &file.url,
+            base: l3repo.source.as_ref().map(|source| source.to_string()),
+            comp_directory: l3repo.comp_directory.as_str(),
+            resolved: l3repo.download_url(file)?.to_string(),
+        });
+    }
+    if format == OutputFormat::Text {
+        for explanation in &explanations {
+            println!(
+                "{} {} {}",
+                explanation.component, explanation.version, explanation.file_name
+            );
+            println!("\tURL:            {}", explanation.url);
+            println!(
+                "\tJoined to:      {}",
+                explanation.base.as_deref().unwrap_or("(none)")
+            );
+            println!("\tComp directory: {}", explanation.comp_directory);
+            println!("\tResolved:       {}", explanation.resolved);
+        }
+    } else {
+        format.print(&explanations)?;
+    }
+    outcomes.finish("explain")
+}
+
+/// A component's part of the fetch plan, for --components-json
+#[derive(Serialize)]
+struct PlannedComponent<'a> {
+    component: String,
+    version: &'a str,
+    files: Vec<PlannedFile<'a>>,
+}
+
+#[derive(Serialize)]
+struct PlannedFile<'a> {
+    url: String,
+    file_name: &'a str,
+    size: u32,
+    checksum: &'a str,
+    checksum_type: &'a str,
+}
+
+/// Print the components fetch would download and their packages, with
+/// everything needed to download and check them elsewhere
+pub fn components_json(l3repo: &L3Repo, action_data: &Action) -> Result<()> {
+    let mut outcomes = Outcomes::new(action_data.keep_going());
+    let files = get_selected_files(l3repo, action_data, &mut outcomes)?;
+    let mut plan: Vec<PlannedComponent> = Vec::new();
+    // Files come grouped by component
+    for (component_id, component_ver, file) in files {
+        let planned_file = PlannedFile {
+            url: l3repo.download_url(file)?.to_string(),
+            file_name: &file.file_name,
+            size: file.size,
+            checksum: &file.checksum,
+            checksum_type: &file.checksum_type,
+        };
+        match plan.last_mut() {
+            Some(planned)
+                if planned.component == component_id
+                    && planned.version == component_ver.version =>
+            {
+                planned.files.push(planned_file)
+            }
+            _ => plan.push(PlannedComponent {
+                component: component_id,
+                version: &component_ver.version,
+                files: vec![planned_file],
+            }),
+        }
+    }
+    println!("{}", serde_json::to_string_pretty(&plan)?);
+    outcomes.finish("plan")
+}
+
+/// Every package file to fetch for the selection, with the component and
+/// version it belongs to.  Unknown components are recorded as failures.
+fn get_fetch_files<'a>(
+    l3repo: &'a L3Repo,
+    action_data: &Action,
+    outcomes: &mut Outcomes,
+) -> Result<
+    Vec<(
+        String,
+        &'a L3ComponentVersion,
+        &'a L3ComponentVersionDownloadFile,
+    )>,
+> {
+    let mut files = Vec::new();
+    for (component_id, opt_ver) in get_component_ids(l3repo, action_data) {
+        let component = match l3repo.get_component(&component_id) {
+            Some(component) => component,
+            None => {
+                let e = Error::InvalidComponent(component_id.to_string());
+                if action_data.keep_going() {
+                    error!("{}", e);
+                }
+                outcomes.record(Err(e))?;
+                continue;
+            }
+        };
+        if opt_ver.is_none() && !component.versions.is_empty() {
+            warn!(
+                "No version specified for component {}. Using first available.",
+                component_id
+            );
+        }
+        if let Some(component_ver) = component.selected_version(opt_ver.as_deref()) {
+            if !matches_target_hw(action_data, component_ver) {
+                debug!(
+                    "Skipping {} version {}, it doesn't support the requested target hardware",
+                    component_id, component_ver.version
+                );
+                continue;
+            }
+            for file in os_download_files(action_data, &component_id, component_ver) {
+                files.push((component_id.clone(), component_ver, file));
+            }
+        } else if let Some(version) = &opt_ver {
+            let e = no_matching_version(component, version);
+            if action_data.keep_going() {
+                error!("{}", e);
+            }
+            outcomes.record(Err(e))?;
+        }
+    }
+    Ok(files)
+}
+
+pub fn fetch(l3repo: &L3Repo, action_data: &Action, cache_dir: &Path) -> Result<()> {
+    let release = &l3repo.information.release;
+    let output_dir = action_data.get_output_dir().unwrap_or(cache_dir);
+    debug!(
+        "Creating output directory {} (if it doesn't already exist)",
+        output_dir.to_string_lossy()
+    );
+    cache::create_cache_dir_all(output_dir)?;
+    for component_id in action_data.get_components() {
+        let (id, _) = split_version(component_id);
+        get_selectable_component(l3repo, &id, action_data.include_hidden())?;
+    }
+    let mut outcomes = Outcomes::new(action_data.keep_going());
+    let files = get_selected_files(l3repo, action_data, &mut outcomes)?;
+    let selection = SyncSelection::new(&files, action_data, output_dir);
+    if action_data.skip_if_unchanged() {
+        match LastSync::load(cache_dir) {
+            Ok(last_sync) if last_sync.matches(release, &selection) => {
+                if !output::is_quiet() {
+                    println!(
+                        "Release {} unchanged since last sync at {} (build {} revision {})",
+                        release.title,
+                        last_sync.synced_at.to_rfc3339(),
+                        release.release_build,
+                        release.release_revision
+                    );
+                }
+                return Ok(());
+            }
+            Ok(last_sync) if last_sync.selection.as_ref() != Some(&selection) => {
+                info!("Selection changed since the last sync, fetching")
+            }
+            Ok(last_sync) => info!(
+                "Release {} changed from build {} revision {}, fetching",
+                release.title, last_sync.release_build, last_sync.release_revision
+            ),
+            Err(e) => debug!("No record of a previous sync: {}", e),
+        }
+    }
+    let started = std::time::Instant::now();
+    if !output::is_quiet() {
+        print_release_summary(&l3repo.information.release);
+    }
+    let mut summary = FetchSummary::default();
+    let mut manifest = FetchManifest {
+        release: (&l3repo.information.release).into(),
+        packages: Vec::new(),
+    };
+    // Which component version placed each path, to catch packages that
+    // would overwrite each other
+    let mut placed: HashMap<PathBuf, (String, String)> = HashMap::new();
+    let total_size: u64 = files.iter().map(|(_, _, file)| u64::from(file.size)).sum();
+    let total_pbar = output::progress_bar(total_size);
+    total_pbar.set_style(
+        indicatif::ProgressStyle::default_bar()
+            .template("{msg} {bar:40} {bytes} of {total_bytes} ({eta} remaining)"),
+    );
+    // Files come grouped by component, in id order
+    let component_count = files
+        .iter()
+        .map(|(component_id, _, _)| component_id)
+        .collect::<HashSet<_>>()
+        .len();
+    let mut component_index = 0;
+    let mut current_component: Option<String> = None;
+    for (component_id, component_ver, file) in files {
+        if current_component.as_ref() != Some(&component_id) {
+            component_index += 1;
+            info!(
+                "Fetching component {} of {}: {}",
+                component_index, component_count, component_id
+            );
+            total_pbar.set_message(&format!(
+                "component {} of {}",
+                component_index, component_count
+            ));
+            current_component = Some(component_id.clone());
+        }
+        let local_filename = match action_data.layout().package_path(
+            output_dir,
+            &component_id,
+            &component_ver.version,
+            &file.file_name,
+        ) {
+            Ok(local_filename) => local_filename,
+            Err(e) => {
+                if action_data.keep_going() {
+                    error!("{}", e);
+                }
+                outcomes.record(Err(e))?;
+                continue;
+            }
+        };
+        let owner = (component_id.clone(), component_ver.version.clone());
+        if let Some((other_id, other_ver)) = placed.get(&local_filename) {
+            if *other_id != owner.0 || *other_ver != owner.1 {
+                warn!(
+                    "Package {} of {} {} replaces the one from {} {}, use --layout hierarchical to keep both",
+                    file.file_name, owner.0, owner.1, other_id, other_ver
+                );
+            }
+        }
+        placed.insert(local_filename.clone(), owner);
+        let present = action_data.only_missing()
+            && validate_file(&local_filename, &file.checksum_type, &file.checksum, None).is_ok();
+        let result = if present {
+            info!(
+                "Skipping {} package {}, already present and valid",
+                component_id, file.file_name
+            );
+            std::fs::metadata(&local_filename)
+                .map(|meta| Transfer {
+                    bytes: meta.len(),
+                    from_cache: true,
+                })
+                .map_err(Error::from)
+        } else if let Some(min_free) = action_data.min_free_space() {
+            // Downloads land in the http cache, unless already there, and
+            // copies also in the output directory
+            let http_cache = cache::get_cache_dir(Some(Path::new("http_cache")));
+            cache::create_cache_dir_all(&http_cache)?;
+            let size = u64::from(file.size);
+            let cached =
+                !action_data.repair() && cache::is_cached(l3repo.download_url(file)?.as_str());
+            let mut writes = vec![(http_cache.as_path(), if cached { 0 } else { size })];
+            if action_data.copy_files() {
+                writes.push((output_dir, size));
+            }
+            // Running out of space isn't something to keep going past
+            check_free_space(&writes, min_free)?;
+            fetch_file(l3repo, action_data, &component_id, file, &local_filename)
+        } else {
+            fetch_file(l3repo, action_data, &component_id, file, &local_filename)
+        };
+        total_pbar.inc(u64::from(file.size));
+        if let Ok(transfer) = &result {
+            summary.add(&component_id, transfer);
+        }
+        let result = result.map(|_| ());
+        match &result {
+            Ok(()) => manifest.packages.push(ManifestEntry {
+                component: component_id.clone(),
+                version: component_ver.version.clone(),
+                file_name: &file.file_name,
+                url: l3repo.download_url(file)?.to_string(),
+                size: file.size,
+                checksum: &file.checksum,
+                checksum_type: &file.checksum_type,
+            }),
+            Err(e) => {
+                if action_data.keep_going() {
+                    error!("{}", e);
+                }
+            }
+        }
+        outcomes.record(result)?;
+    }
+    total_pbar.finish_and_clear();
+    if action_data.flatten_symlinks() {
+        let flattened = cache::flatten_symlinks(output_dir)?;
+        info!(
+            "Replaced {} symlinks in {} with files",
+            flattened,
+            output_dir.display()
+        );
+    }
+    if !output::is_quiet() {
+        summary.print(started.elapsed());
+    }
+    write_manifest(output_dir, &manifest)?;
+    if let Some(path) = action_data.get_lockfile() {
+        if !action_data.locked() {
+            Lockfile {
+                product_category: release.product_category.clone(),
+                target_os: release.target_os.clone(),
+                release: release.title.clone(),
+                packages: manifest
+                    .packages
+                    .iter()
+                    .map(|entry| LockedPackage {
+                        component: entry.component.clone(),
+                        version: entry.version.clone(),
+                        file_name: entry.file_name.to_string(),
+                        checksum: entry.checksum.to_string(),
+                        checksum_type: entry.checksum_type.to_string(),
+                    })
+                    .collect(),
+            }
+            .save(path)?;
+        }
+    }
+    outcomes.finish("fetch")?;
+    let last_sync = LastSync {
+        release_build: release.release_build.clone(),
+        release_revision: release.release_revision,
+        selection: Some(selection),
+        synced_at: offset::Utc::now(),
+    };
+    // Failing to record it only costs the next --skip-if-unchanged a fetch
+    if let Err(e) = last_sync.save(cache_dir) {
+        warn!("Failed recording the synced release build: {}", e);
+    }
+    Ok(())
+}
+
+/// Make sure writing each `(dir, size)` of `writes` leaves at least
+/// `min_free` bytes free on the dir.  Dirs with nothing to write aren't
+/// checked.
+fn check_free_space(writes: &[(&Path, u64)], min_free: u64) -> Result<()> {
+    for &(dir, size) in writes.iter().filter(|(_, size)| *size > 0) {
+        let available = cache::available_space(dir)?;
+        let required = size + min_free;
+        if available < required {
+            return Err(Error::InsufficientDiskSpace {
+                path: dir.to_string_lossy().to_string(),
+                available,
+                required,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Digest of a file, using the one recorded when it was downloaded into the
+/// http cache if the file hasn't changed since
+fn get_digest(filename: &Path, checksum_type: &str) -> Result<String> {
+    match caching_client::recorded_digest(filename, checksum_type) {
+        Some(digest) => {
+            debug!("Using checksum recorded at download for {:?}", filename);
+            Ok(digest)
+        }
+        None => checksum::compute_digest(filename, checksum_type),
+    }
+}
+
+/// Check a file against its expected checksum, reusing digests from
+/// `digests` for files unchanged since they were last hashed
+fn validate_file(
+    filename: &Path,
+    checksum_type: &str,
+    checksum: &str,
+    digests: Option<&mut DigestCache>,
+) -> Result<()> {
+    if !filename.exists() {
+        return Err(Error::FileNotExist(filename.to_string_lossy().to_string()));
+    }
+
+    info!("Verifying file checksum...");
+    let digest_str = match digests {
+        Some(digests) => digests.digest(filename, checksum_type, || {
+            get_digest(filename, checksum_type)
+        })?,
+        None => get_digest(filename, checksum_type)?,
+    };
+    if !checksum::digests_match(&digest_str, checksum) {
+        return Err(Error::FileDigestInvalid {
+            file: filename.to_string_lossy().to_string(),
+            cktype: checksum_type.to_string(),
+            expected: checksum.to_string(),
+            actual: digest_str,
+        });
+    }
+    Ok(())
+}
+
+/// Check a file has the size the repo declares, without hashing it
+fn validate_file_size(filename: &Path, size: u32) -> Result<()> {
+    if !filename.exists() {
+        return Err(Error::FileNotExist(filename.to_string_lossy().to_string()));
+    }
+    let actual = caching_client::data_size(filename)?;
+    if actual != u64::from(size) {
+        return Err(Error::FileSizeInvalid {
+            file: filename.to_string_lossy().to_string(),
+            expected: u64::from(size),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Result of verifying one package file, for machine-readable output
+#[derive(Serialize)]
+struct VerifyReport {
+    file: String,
+    status: &'static str,
+    expected: String,
+    actual: Option<String>,
+}
+
+/// `expected` is the checksum, or with --size-only the size
+fn verify_report(filename: &Path, expected: &str, result: &Result<()>) -> Option<VerifyReport> {
+    let (status, actual) = match result {
+        Ok(()) => ("valid", Some(expected.to_string())),
+        Err(Error::FileDigestInvalid { actual, .. }) => ("invalid", Some(actual.clone())),
+        Err(Error::FileSizeInvalid { actual, .. }) => ("size-mismatch", Some(actual.to_string())),
+        Err(Error::FileNotExist(_)) => ("missing", None),
+        Err(Error::UnsupportedChecksumType(_)) => ("unchecked", None),
+        Err(_) => return None,
+    };
+    Some(VerifyReport {
+        file: filename.to_string_lossy().to_string(),
+        status,
+        expected: expected.to_string(),
+        actual,
+    })
+}
+
+/// Tally of a verify for the closing summary
+#[derive(Default, Serialize)]
+struct VerifySummary {
+    checked: usize,
+    valid: usize,
+    invalid: usize,
+    missing: usize,
+    /// Packages with a checksum type there's no verifier for
+    unchecked: usize,
+    bytes_verified: u64,
+}
+
+impl VerifySummary {
+    fn add(&mut self, result: &Result<()>, size: u32) {
+        self.checked += 1;
+        match result {
+            Ok(()) => {
+                self.valid += 1;
+                self.bytes_verified += u64::from(size);
+            }
+            Err(Error::FileNotExist(_)) => self.missing += 1,
+            Err(Error::UnsupportedChecksumType(_)) => self.unchecked += 1,
+            Err(_) => self.invalid += 1,
+        }
+    }
+
+    fn print(&self) {
+        println!("Checked {} packages", self.checked);
+        println!("\tValid:     {}", self.valid);
+        println!("\tInvalid:   {}", self.invalid);
+        println!("\tMissing:   {}", self.missing);
+        if self.unchecked > 0 {
+            println!("\tUnchecked: {}", self.unchecked);
+        }
+        println!(
+            "\tVerified:  {}",
+            indicatif::HumanBytes(self.bytes_verified)
+        );
+    }
+}
+
+/// Machine-readable output of verify
+#[derive(Serialize)]
+struct VerifyOutput<'a> {
+    files: &'a [VerifyReport],
+    summary: &'a VerifySummary,
+}
+
+pub fn verify(
+    l3repo: &L3Repo,
+    action_data: &Action,
+    cache_dir: &Path,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut reports = Vec::new();
+    let mut summary = VerifySummary::default();
+    let mut digests = if action_data.force() {
+        DigestCache::new(cache_dir)
+    } else {
+        DigestCache::load(cache_dir)
+    };
+    let result = verify_files(
+        l3repo,
+        action_data,
+        cache_dir,
+        &mut digests,
+        &mut reports,
+        &mut summary,
+    );
+    if let Err(e) = digests.save() {
+        warn!("Failed saving verified checksums: {}", e);
+    }
+    if format != OutputFormat::Text {
+        format.print(&VerifyOutput {
+            files: &reports,
+            summary: &summary,
+        })?;
+    } else if !output::is_quiet() {
+        summary.print();
+    }
+    result
+}
+
+fn verify_files(
+    l3repo: &L3Repo,
+    action_data: &Action,
+    cache_dir: &Path,
+    digests: &mut DigestCache,
+    reports: &mut Vec<VerifyReport>,
+    summary: &mut VerifySummary,
+) -> Result<()> {
+    let mut outcomes = Outcomes::new(action_data.keep_going());
+    for (component_id, opt_ver) in get_component_ids(l3repo, action_data) {
+        let component = match l3repo.get_component(&component_id) {
+            Some(component) => component,
+            None => {
+                let e = Error::InvalidComponent(component_id.to_string());
+                if action_data.keep_going() {
+                    error!("{}", e);
+                }
+                outcomes.record(Err(e))?;
+                continue;
+            }
+        };
+
+        if opt_ver.is_none() && !component.versions.is_empty() {
+            warn!(
+                "No version specified for component {}. Using first available.",
+                component_id
+            );
+        }
+        if let Some(version) = component.selected_version(opt_ver.as_deref()) {
+            for file in os_download_files(action_data, &component_id, version) {
+                let local_filename = match action_data.layout().package_path(
+                    cache_dir,
+                    &component_id,
+                    &version.version,
+                    &file.file_name,
+                ) {
+                    Ok(local_filename) => local_filename,
+                    Err(e) => {
+                        if action_data.keep_going() {
+                            error!("{}", e);
+                        }
+                        outcomes.record(Err(e))?;
+                        continue;
+                    }
+                };
+                let check = |digests: &mut DigestCache| {
+                    if action_data.size_only() {
+                        validate_file_size(&local_filename, file.size)
+                    } else {
+                        validate_file(
+                            &local_filename,
+                            &file.checksum_type,
+                            &file.checksum,
+                            Some(digests),
+                        )
+                    }
+                };
+                let expected = if action_data.size_only() {
+                    file.size.to_string()
+                } else {
+                    file.checksum.clone()
+                };
+                let result = check(digests);
+                match &result {
+                    Ok(()) if action_data.size_only() => {
+                        info!("OK:      {}", local_filename.to_string_lossy())
+                    }
+                    Ok(()) => info!("VALID:   {}", local_filename.to_string_lossy()),
+                    Err(Error::FileSizeInvalid {
+                        file: f,
+                        expected: e,
+                        actual: a,
+                    }) => error!("SIZE-MISMATCH: {} is {} bytes, expected {}", f, a, e),
+                    Err(Error::FileDigestInvalid {
+                        file: f,
+                        cktype: ct,
+                        expected: c,
+                        actual: d,
+                    }) => error!("INVALID DIGEST: {}[{}] {} != {}", f, ct, d, c),
+                    Err(Error::FileNotExist(f)) if action_data.checksum_only() => {
+                        info!("SKIPPED: {} does not exist", f)
+                    }
+                    Err(Error::FileNotExist(f)) if action_data.size_only() => {
+                        error!("MISSING: {} does not exist", f)
+                    }
+                    Err(Error::FileNotExist(f)) => error!("MISSING FILE:   {} does not exist", f),
+                    Err(Error::UnsupportedChecksumType(ct)) if !action_data.strict() => warn!(
+                        "UNCHECKED: {} has unsupported checksum type {}",
+                        local_filename.to_string_lossy(),
+                        ct
+                    ),
+                    Err(e) if action_data.keep_going() => error!("{}", e),
+                    Err(_) => {}
+                }
+                if action_data.checksum_only() && matches!(result, Err(Error::FileNotExist(_))) {
+                    summary.add(&result, file.size);
+                    continue;
+                }
+                // Not a failure of the package, only of our ability to
+                // check it, unless --strict says otherwise
+                if matches!(result, Err(Error::UnsupportedChecksumType(_))) {
+                    if action_data.strict() {
+                        return result;
+                    }
+                    reports.extend(verify_report(&local_filename, &expected, &result));
+                    summary.add(&result, file.size);
+                    continue;
+                }
+                let repairable = matches!(
+                    result,
+                    Err(Error::FileDigestInvalid { .. })
+                        | Err(Error::FileSizeInvalid { .. })
+                        | Err(Error::FileNotExist(_))
+                );
+                let (result, repaired) = if repairable && action_data.repair() {
+                    info!("REPAIRING: {}", local_filename.to_string_lossy());
+                    let repaired =
+                        fetch_file(l3repo, action_data, &component_id, file, &local_filename)
+                            .and_then(|_| check(digests));
+                    match &repaired {
+                        Ok(()) => info!("REPAIRED: {}", local_filename.to_string_lossy()),
+                        Err(e) if action_data.keep_going() => error!("{}", e),
+                        Err(_) => {}
+                    }
+                    (repaired, true)
+                } else {
+                    (result, false)
+                };
+                let report = verify_report(&local_filename, &expected, &result);
+                reports.extend(report.map(|mut report| {
+                    if repaired && result.is_ok() {
+                        report.status = "repaired";
+                    } else if action_data.size_only() && result.is_ok() {
+                        report.status = "ok";
+                    }
+                    report
+                }));
+                summary.add(&result, file.size);
+                outcomes.record(result)?;
+            }
+        } else if let Some(version) = &opt_ver {
+            let e = no_matching_version(component, version);
+            if action_data.keep_going() {
+                error!("{}", e);
+            }
+            outcomes.record(Err(e))?;
+        }
+    }
+    outcomes.finish("verify")
 }
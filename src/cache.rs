@@ -1,12 +1,28 @@
 // Needed to bring in Read trait
 use std::io::Read;
 
+use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
 
 use lazy_static::lazy_static;
+use log::{debug, warn};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use serde::de::DeserializeOwned;
 
-use crate::caching_client::{CacheType, CachedRequestBuilder};
-use crate::error::Result;
+use crate::caching_client::{self, CacheType, CachedRequestBuilder, CachedResponse};
+use crate::error::{Error, Result};
+use crate::host_override::{self, HostOverride};
+use crate::netrc;
+
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+pub const DEFAULT_METADATA_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// reqwest's own limit
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
 
 pub fn get_cache_dir(sub_path_opt: Option<&Path>) -> PathBuf {
     let mut dir = dirs::cache_dir().expect("Failed getting local user cache directory");
@@ -17,28 +33,641 @@ pub fn get_cache_dir(sub_path_opt: Option<&Path>) -> PathBuf {
     dir
 }
 
+/// Move a cached download into the content-addressed store under
+/// `http_cache/by-hash/<checksum_type>/<digest>`, leaving a symlink in its
+/// place.  If the store already holds that content, the cached copy is
+/// dropped in favor of a link to the existing blob.  Compressed data is
+/// stored apart from uncompressed, with the compression's extension.
+/// Returns the blob path.
+pub fn dedup_cached_file(cached_file: &Path, checksum_type: &str, digest: &str) -> Result<PathBuf> {
+    let blob_dir = get_cache_dir(Some(&Path::new("http_cache/by-hash").join(checksum_type)));
+    let blob = match caching_client::data_compression(cached_file) {
+        Some(compression) => blob_dir.join(format!("{}.{}", digest, compression.extension())),
+        None => blob_dir.join(digest),
+    };
+    if std::fs::symlink_metadata(cached_file)?
+        .file_type()
+        .is_symlink()
+    {
+        // Already deduplicated on a previous run
+        return Ok(blob);
+    }
+    create_cache_dir_all(&blob_dir)?;
+    if blob.exists() {
+        debug!(
+            "{} duplicates {}, linking to it",
+            cached_file.display(),
+            blob.display()
+        );
+        std::fs::remove_file(cached_file)?;
+    } else {
+        debug!("Moving {} to {}", cached_file.display(), blob.display());
+        std::fs::rename(cached_file, &blob)?;
+    }
+    symlink(&blob, cached_file)?;
+    Ok(blob)
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`
+pub fn available_space(path: &Path) -> Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| Error::from(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+    // Safety: c_path is a valid C string and stat is only read after
+    // statvfs fills it in
+    let stat = unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(c_path.as_ptr(), &mut stat) != 0 {
+            return Err(Error::from(std::io::Error::last_os_error()));
+        }
+        stat
+    };
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Total size of the files under `dir`, not following links
+pub fn dir_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Replace the symlinks under `dir` with hard links to the files they
+/// resolve to, or with copies where a hard link can't be made, such as
+/// across filesystems or to compressed data.  Links that don't resolve are
+/// left alone.  Returns how many links were replaced.
+pub fn flatten_symlinks(dir: &Path) -> Result<usize> {
+    let mut flattened = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            flattened += flatten_symlinks(&path)?;
+            continue;
+        }
+        if !file_type.is_symlink() {
+            continue;
+        }
+        let target = match std::fs::canonicalize(&path) {
+            Ok(target) if target.is_file() => target,
+            _ => {
+                warn!(
+                    "Not flattening {}, it isn't a link to a file",
+                    path.display()
+                );
+                continue;
+            }
+        };
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".flatten");
+        let temp = path.with_file_name(temp_name);
+        if temp.exists() {
+            std::fs::remove_file(&temp)?;
+        }
+        let linked = if caching_client::data_compression(&target).is_some() {
+            false
+        } else {
+            match std::fs::hard_link(&target, &temp) {
+                Ok(()) => true,
+                Err(e) => {
+                    debug!("Can't hard link {}, copying it: {}", target.display(), e);
+                    false
+                }
+            }
+        };
+        if linked {
+            debug!("Hard linked {} to {}", path.display(), target.display());
+        } else {
+            caching_client::copy_data(&target, &temp)?;
+            apply_cache_mode(&temp)?;
+            debug!("Copied {} to {}", target.display(), path.display());
+        }
+        std::fs::rename(&temp, &path)?;
+        flattened += 1;
+    }
+    Ok(flattened)
+}
+
+lazy_static! {
+    static ref CACHE_MODE: RwLock<Option<u32>> = RwLock::new(None);
+}
+
+/// Parse a `--cache-mode` octal permission value such as 2775
+pub fn parse_mode(mode: &str) -> Result<u32> {
+    u32::from_str_radix(mode, 8).map_err(Error::from)
+}
+
+/// Permissions for created cache directories; files get the same bits
+/// without execute.  None leaves them to the umask.
+pub fn set_cache_mode(mode: Option<u32>) {
+    *CACHE_MODE.write().expect("cache mode lock poisoned") = mode;
+}
+
+fn cache_mode() -> Option<u32> {
+    *CACHE_MODE.read().expect("cache mode lock poisoned")
+}
+
+/// Apply the configured cache mode to a created file or directory
+pub fn apply_cache_mode(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Some(mode) = cache_mode() {
+        let mode = if path.is_dir() { mode } else { mode & !0o111 };
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+/// `create_dir_all`, applying the cache mode to each directory it creates
+pub fn create_cache_dir_all(path: &Path) -> Result<()> {
+    let missing: Vec<&Path> = path.ancestors().take_while(|dir| !dir.exists()).collect();
+    std::fs::create_dir_all(path)?;
+    for dir in missing.iter().rev() {
+        apply_cache_mode(dir)?;
+    }
+    Ok(())
+}
+
+/// Settings for the shared http client
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub user_agent: String,
+    /// Always download in full instead of revalidating cached responses
+    pub no_cache: bool,
+    /// Like `no_cache`, but only for the L1/L2/L3 repo JSON requests
+    pub refresh: bool,
+    /// Idle connections to keep open per host, unlimited if unset
+    pub max_idle_per_host: Option<usize>,
+    /// Speak HTTP/2 without negotiating it first
+    pub http2: bool,
+    /// Extra root certificates (PEM or DER) to trust
+    pub ca_certs: Vec<PathBuf>,
+    /// PEM client certificate to authenticate with
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key for `client_cert`, if not in the same file
+    pub client_key: Option<PathBuf>,
+    /// Skip server certificate validation entirely
+    pub danger_accept_invalid_certs: bool,
+    /// Local address to bind connections to.  An unspecified address
+    /// (0.0.0.0 or ::) restricts connections to that address family.
+    pub local_address: Option<std::net::IpAddr>,
+    /// Servers mirroring the package downloads, tried in order.  A
+    /// package's path is looked up under each mirror's url.
+    pub mirrors: Vec<url::Url>,
+    /// Store cached data gzip compressed, decompressing it as it's read
+    pub compress_cache: bool,
+    /// Redirects to follow for a request before giving up on it
+    pub max_redirects: usize,
+    /// Timeout for the L1/L2/L3 repo JSON requests
+    pub metadata_timeout: Option<Duration>,
+    /// Timeout for package downloads, none by default since they can run
+    /// for a long time
+    pub download_timeout: Option<Duration>,
+    /// Extra headers sent with every request, replacing any default of
+    /// the same name
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    /// How long cached L1/L2/L3 repo JSON is used without revalidating
+    /// it, always revalidated if unset
+    pub repo_cache_ttl: Option<Duration>,
+    /// Hosts and ports to connect to at a fixed address instead of
+    /// resolving them
+    pub resolve: Vec<HostOverride>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            no_cache: false,
+            refresh: false,
+            max_idle_per_host: None,
+            http2: false,
+            ca_certs: Vec::new(),
+            client_cert: None,
+            client_key: None,
+            danger_accept_invalid_certs: false,
+            local_address: None,
+            mirrors: Vec::new(),
+            compress_cache: false,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            metadata_timeout: Some(DEFAULT_METADATA_TIMEOUT),
+            download_timeout: None,
+            headers: Vec::new(),
+            repo_cache_ttl: None,
+            resolve: Vec::new(),
+        }
+    }
+}
+
+/// Parse a `--header` value of the form `Name: Value`.  The value is
+/// marked sensitive, as it's often a token, so debug output hides it.
+pub fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue)> {
+    let malformed = || Error::MalformedHeader(header.to_string());
+    let (name, value) = header.split_once(':').ok_or_else(malformed)?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| malformed())?;
+    let mut value = HeaderValue::from_str(value.trim()).map_err(|_| malformed())?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+fn load_certificate(path: &Path) -> Result<reqwest::Certificate> {
+    let data = std::fs::read(path)?;
+    reqwest::Certificate::from_pem(&data)
+        .or_else(|_| reqwest::Certificate::from_der(&data))
+        .map_err(Error::from)
+}
+
+/// The native TLS backend only takes client identities as PKCS #12, so
+/// bundle the PEM certificate and key into one
+fn load_identity(cert_path: &Path, key_path: &Path) -> Result<reqwest::Identity> {
+    let cert = openssl::x509::X509::from_pem(&std::fs::read(cert_path)?)?;
+    let key = openssl::pkey::PKey::private_key_from_pem(&std::fs::read(key_path)?)?;
+    let pkcs12 = openssl::pkcs12::Pkcs12::builder()
+        .name("")
+        .pkey(&key)
+        .cert(&cert)
+        .build2("")?;
+    reqwest::Identity::from_pkcs12_der(&pkcs12.to_der()?, "").map_err(Error::from)
+}
+
+/// Follow up to `max_redirects` redirects, logging each.  Responses are
+/// still cached under the url that was requested, not the one redirected to.
+fn redirect_policy(max_redirects: usize) -> reqwest::RedirectPolicy {
+    reqwest::RedirectPolicy::custom(move |attempt| {
+        if attempt.previous().len() > max_redirects {
+            return attempt.too_many_redirects();
+        }
+        if let Some(from) = attempt.previous().last() {
+            debug!(
+                "Redirect {} of at most {} ({}): {} -> {}",
+                attempt.previous().len(),
+                max_redirects,
+                attempt.status(),
+                from,
+                attempt.url()
+            );
+        }
+        attempt.follow()
+    })
+}
+
+/// `resolve_proxy` is where connections to the `--resolve` hosts go
+fn build_client(
+    options: &ClientOptions,
+    timeout: Option<Duration>,
+    resolve_proxy: Option<&url::Url>,
+) -> Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        USER_AGENT,
+        HeaderValue::from_str(&options.user_agent)
+            .map_err(|_| Error::InvalidHeaderValue(options.user_agent.clone()))?,
+    );
+    for (index, (name, value)) in options.headers.iter().enumerate() {
+        // The first of a name replaces the default.  reqwest keeps only one
+        // value of each default header, so repeats are folded into it as a
+        // comma separated list, which HTTP treats the same.
+        let repeated = options.headers[..index]
+            .iter()
+            .any(|(seen, _)| seen == name);
+        let value = match headers.get(name) {
+            Some(first) if repeated => {
+                let folded = [first.as_bytes(), b", ", value.as_bytes()].concat();
+                let mut folded = HeaderValue::from_bytes(&folded).map_err(|_| {
+                    Error::InvalidHeaderValue(String::from_utf8_lossy(&folded).to_string())
+                })?;
+                folded.set_sensitive(value.is_sensitive());
+                folded
+            }
+            _ => value.clone(),
+        };
+        headers.insert(name.clone(), value);
+    }
+    // reqwest only implements gzip; it advertises it and decodes responses
+    // before they reach the cache, so cached entries are always plain
+    let mut builder = reqwest::Client::builder()
+        .default_headers(headers)
+        .gzip(true)
+        .redirect(redirect_policy(options.max_redirects))
+        .timeout(timeout);
+    if let Some(max_idle) = options.max_idle_per_host {
+        builder = builder.max_idle_per_host(max_idle);
+    }
+    if options.http2 {
+        builder = builder.h2_prior_knowledge();
+    }
+    for ca_cert in &options.ca_certs {
+        debug!("Trusting root certificate {}", ca_cert.display());
+        builder = builder.add_root_certificate(load_certificate(ca_cert)?);
+    }
+    if let Some(client_cert) = &options.client_cert {
+        let client_key = options.client_key.as_ref().unwrap_or(client_cert);
+        builder = builder.identity(load_identity(client_cert, client_key)?);
+    }
+    if let Some(local_address) = options.local_address {
+        // hyper skips remote addresses the bound socket can't reach, so
+        // the other family fails fast instead of hanging
+        builder = builder.local_address(local_address);
+    }
+    if options.danger_accept_invalid_certs {
+        warn!("Accepting invalid TLS certificates, connections are NOT secure");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    if let Some(resolve_proxy) = resolve_proxy {
+        let overrides = options.resolve.clone();
+        // reqwest's url is a different version of the url crate
+        let resolve_proxy = reqwest::Url::parse(resolve_proxy.as_str())
+            .expect("Proxy url should still parse as a reqwest url");
+        builder = builder.proxy(reqwest::Proxy::custom(move |url| {
+            let host = url.host_str()?;
+            let port = url.port_or_known_default()?;
+            if overrides.iter().any(|o| o.matches(host, port)) {
+                Some(resolve_proxy.clone())
+            } else {
+                None
+            }
+        }));
+    }
+    builder.build().map_err(Error::from)
+}
+
+/// reqwest 0.9 only sets timeouts per client, so each kind of request gets
+/// its own client
+struct HttpClients {
+    metadata: reqwest::Client,
+    download: reqwest::Client,
+}
+
+impl HttpClients {
+    fn new(options: &ClientOptions) -> Result<Self> {
+        // Both clients share the one proxy for the --resolve hosts
+        let resolve_proxy = if options.resolve.is_empty() {
+            None
+        } else {
+            Some(host_override::start_proxy(
+                &options.resolve,
+                options.local_address,
+            )?)
+        };
+        Ok(Self {
+            metadata: build_client(options, options.metadata_timeout, resolve_proxy.as_ref())?,
+            download: build_client(options, options.download_timeout, resolve_proxy.as_ref())?,
+        })
+    }
+}
+
 lazy_static! {
-    static ref HTTP: reqwest::Client = reqwest::Client::new();
+    static ref HTTP: RwLock<HttpClients> = RwLock::new(
+        HttpClients::new(&ClientOptions::default()).expect("Failed building default http clients")
+    );
+    static ref OPTIONS: RwLock<ClientOptions> = RwLock::new(ClientOptions::default());
+}
+
+/// Replace the shared http clients with ones built from `options`
+pub fn configure_client(options: &ClientOptions) -> Result<()> {
+    let clients = HttpClients::new(options)?;
+    *HTTP.write().expect("http client lock poisoned") = clients;
+    *OPTIONS.write().expect("client options lock poisoned") = options.clone();
+    Ok(())
+}
+
+fn metadata_client() -> reqwest::Client {
+    HTTP.read()
+        .expect("http client lock poisoned")
+        .metadata
+        .clone()
+}
+
+fn download_client() -> reqwest::Client {
+    HTTP.read()
+        .expect("http client lock poisoned")
+        .download
+        .clone()
+}
+
+fn client_options() -> ClientOptions {
+    OPTIONS
+        .read()
+        .expect("client options lock poisoned")
+        .clone()
 }
 
-pub fn cached_get_path(url_str: &str) -> Result<PathBuf> {
-    let req = HTTP.get(url_str);
+/// Request `url_str` without involving the cache, returning how long the
+/// response took
+pub fn check_reachable(url_str: &str) -> Result<Duration> {
+    let started = std::time::Instant::now();
+    let resp = metadata_client().get(url_str).send()?;
+    let elapsed = started.elapsed();
+    if !resp.status().is_success() {
+        return Err(Error::HttpStatus {
+            url: url_str.to_string(),
+            status: resp.status(),
+        });
+    }
+    Ok(elapsed)
+}
+
+/// A url's data on disk
+pub struct CachedPath {
+    pub path: PathBuf,
+    /// The cached copy was still current, nothing was downloaded
+    pub from_cache: bool,
+    /// Where a no-store body was written outside the cache, removed with
+    /// this
+    _uncached: Option<caching_client::UncachedDir>,
+}
+
+/// A download request authenticated with `credentials` if given, else with
+/// those from the user's netrc file when it has an entry for the host
+fn download_request(
+    client: &reqwest::Client,
+    url_str: &str,
+    credentials: Option<&netrc::Credentials>,
+) -> reqwest::RequestBuilder {
+    let req = client.get(url_str);
+    if let Some(credentials) = credentials {
+        debug!("Using repo credentials for {}", url_str);
+        return req.basic_auth(&credentials.login, credentials.password.as_ref());
+    }
+    let host = url::Url::parse(url_str)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string));
+    match host.as_deref().and_then(netrc::credentials) {
+        Some(credentials) => {
+            debug!("Using netrc credentials for {}", url_str);
+            req.basic_auth(&credentials.login, credentials.password.as_ref())
+        }
+        None => req,
+    }
+}
+
+/// Where to download `url_str` from: each mirror in turn, with the url's
+/// path under the mirror's, or just the url itself without mirrors
+fn download_urls(url_str: &str, mirrors: &[url::Url]) -> Result<Vec<String>> {
+    if mirrors.is_empty() {
+        return Ok(vec![url_str.to_string()]);
+    }
+    let url = url::Url::parse(url_str)?;
+    mirrors
+        .iter()
+        .map(|mirror| {
+            // Join under the mirror's path rather than beside it
+            let mut mirror = mirror.clone();
+            if !mirror.path().ends_with('/') {
+                mirror.set_path(&format!("{}/", mirror.path()));
+            }
+            let mut mirrored = mirror.join(url.path().trim_start_matches('/'))?;
+            mirrored.set_query(url.query());
+            Ok(mirrored.to_string())
+        })
+        .collect()
+}
+
+fn download_path(
+    url_str: &str,
+    credentials: Option<&netrc::Credentials>,
+    no_cache: bool,
+) -> Result<CachedPath> {
+    let client = download_client();
+    let req = download_request(&client, url_str, credentials);
     let mut c_resp = CachedRequestBuilder::new(
         CacheType::Private,
         &get_cache_dir(Some(Path::new("http_cache"))),
         req,
     )
-    .send(&HTTP)?;
-    c_resp.cached_file_path()
+    .no_cache(no_cache)
+    .compress(client_options().compress_cache)
+    .send(&client)?;
+    let path = c_resp.cached_file_path()?;
+    Ok(CachedPath {
+        from_cache: c_resp.served_from_cache(),
+        path,
+        _uncached: c_resp.take_uncached(),
+    })
 }
 
-pub fn cached_get_reader(url_str: &str) -> Result<impl Read> {
-    let req = HTTP.get(url_str);
-    let mut c_resp = CachedRequestBuilder::new(
+/// Download a single document, like release notes, that the package
+/// mirrors don't carry, returning the path of the cached file
+pub fn cached_get_document(url_str: &str) -> Result<CachedPath> {
+    download_path(url_str, None, client_options().no_cache)
+}
+
+/// Download through each configured mirror until one succeeds.  Only
+/// connection failures and error statuses move on to the next mirror.
+fn mirrored_download_path(
+    url_str: &str,
+    credentials: Option<&netrc::Credentials>,
+    no_cache: bool,
+) -> Result<CachedPath> {
+    let urls = download_urls(url_str, &client_options().mirrors)?;
+    let mut last_error = None;
+    for candidate in &urls {
+        // The credentials belong to the original host
+        let credentials = credentials.filter(|_| candidate == url_str);
+        match download_path(candidate, credentials, no_cache) {
+            Ok(cached) => {
+                if urls.len() > 1 {
+                    debug!("{} served by {}", url_str, candidate);
+                }
+                return Ok(cached);
+            }
+            Err(e @ Error::HttpError(_)) | Err(e @ Error::HttpStatus { .. }) => {
+                warn!("Download from {} failed: {}", candidate, e);
+                last_error = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(last_error.expect("at least one download url"))
+}
+
+pub fn cached_get_path(
+    url_str: &str,
+    credentials: Option<&netrc::Credentials>,
+) -> Result<CachedPath> {
+    mirrored_download_path(url_str, credentials, client_options().no_cache)
+}
+
+/// Whether `url_str`, directly or through a configured mirror, already
+/// has data in the http cache that a download would only revalidate
+pub fn is_cached(url_str: &str) -> bool {
+    let options = client_options();
+    if options.no_cache {
+        return false;
+    }
+    let cache_dir = get_cache_dir(Some(Path::new("http_cache")));
+    download_urls(url_str, &options.mirrors)
+        .map(|urls| {
+            urls.iter()
+                .any(|url| caching_client::url_data_cache_path(&cache_dir, url).is_file())
+        })
+        .unwrap_or(false)
+}
+
+/// Like `cached_get_path`, but always downloads the url in full, replacing
+/// whatever was cached for it
+pub fn refetch_path(url_str: &str, credentials: Option<&netrc::Credentials>) -> Result<CachedPath> {
+    mirrored_download_path(url_str, credentials, true)
+}
+
+fn cached_get(url_str: &str) -> Result<CachedResponse> {
+    let client = metadata_client();
+    let options = client_options();
+    let req = client.get(url_str);
+    CachedRequestBuilder::new(
         CacheType::Private,
         &get_cache_dir(Some(Path::new("http_cache"))),
         req,
     )
-    .send(&HTTP)?;
-    c_resp.cached_reader()
+    .no_cache(options.no_cache || options.refresh)
+    .compress(options.compress_cache)
+    .send(&client)
+}
+
+/// The cached data of `url_str` if it's within the repo cache ttl, unless
+/// the options say to revalidate
+fn fresh_cached_path(url_str: &str) -> Option<PathBuf> {
+    let options = client_options();
+    if options.no_cache || options.refresh {
+        return None;
+    }
+    caching_client::fresh_data_path(
+        &get_cache_dir(Some(Path::new("http_cache"))),
+        url_str,
+        options.repo_cache_ttl?,
+    )
+}
+
+pub fn cached_get_reader(url_str: &str) -> Result<impl Read> {
+    if let Some(path) = fresh_cached_path(url_str) {
+        return caching_client::open_data(&path);
+    }
+    cached_get(url_str)?.cached_reader()
+}
+
+/// Deserialize the json document cached for `url_str`, however old, without
+/// asking the server
+pub fn cached_copy_json<T: DeserializeOwned>(url_str: &str) -> Result<T> {
+    let path =
+        caching_client::url_data_cache_path(&get_cache_dir(Some(Path::new("http_cache"))), url_str);
+    serde_json::from_reader(caching_client::open_data(&path)?).map_err(Error::from)
+}
+
+/// Deserialize the json document at `url_str` straight from its cached
+/// file
+pub fn cached_get_json<T: DeserializeOwned>(url_str: &str) -> Result<T> {
+    if let Some(path) = fresh_cached_path(url_str) {
+        return serde_json::from_reader(caching_client::open_data(&path)?).map_err(Error::from);
+    }
+    cached_get(url_str)?.cached_json_from_reader()
 }
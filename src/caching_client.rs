@@ -6,36 +6,115 @@
 ///
 /// TODO:
 /// * Allow setting maximum cache size policy
-/// * Honor cache-control header
+/// * Honor cache-control freshness directives (only no-store
+///   is currently honored)
 /// * Honor public/private policy
 /// * Verify the cached data length against the content-length
 ///   header in metadata
-/// * Pass the url of the request into the cached response object
-///   and use that instead of relying on the response url, since
-///   redirects and other things could cause cache misses due to
-///   request/response name mismatches
 // Needed to bring in Read trait
-use std::io::Read;
+use std::io::{Read, Write};
 
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::hash::Hasher;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 
 use chrono::{offset, DateTime};
 use encoding_rs::{Encoding, UTF_8};
-use log::{debug, info};
-use reqwest::header::{CONTENT_TYPE, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use flate2::write::GzEncoder;
+use log::{debug, info, warn};
+use reqwest::header::{
+    CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+};
 use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use crate::cache;
 use crate::error::{Error, Result};
+use crate::interrupt::PartialFile;
 
+/// Hash of a url naming its cache entry directory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheKeyHash {
+    /// 64 bit FNV-1a, fast and not a cryptographic hash
+    Fnv,
+    /// What every cache entry was named with before the hash was
+    /// configurable
+    Md5,
+}
+
+impl CacheKeyHash {
+    pub fn variants() -> Vec<String> {
+        vec!["fnv".to_string(), "md5".to_string()]
+    }
+
+    /// The cache key of `url`
+    pub fn key(self, url: &str) -> String {
+        match self {
+            CacheKeyHash::Fnv => {
+                let mut hasher = fnv::FnvHasher::default();
+                hasher.write(url.as_bytes());
+                format!("{:016x}", hasher.finish())
+            }
+            CacheKeyHash::Md5 => format!("{:x}", md5::compute(url.as_bytes())),
+        }
+    }
+}
+
+impl FromStr for CacheKeyHash {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "fnv" => Ok(CacheKeyHash::Fnv),
+            "md5" => Ok(CacheKeyHash::Md5),
+            _ => Err(Error::InvalidCacheKeyHash(s.to_string(), Self::variants())),
+        }
+    }
+}
+
+static CACHE_KEY_HASH: AtomicU8 = AtomicU8::new(CacheKeyHash::Fnv as u8);
+
+pub fn set_cache_key_hash(hash: CacheKeyHash) {
+    CACHE_KEY_HASH.store(hash as u8, Ordering::Relaxed);
+}
+
+pub fn cache_key_hash() -> CacheKeyHash {
+    match CACHE_KEY_HASH.load(Ordering::Relaxed) {
+        h if h == CacheKeyHash::Md5 as u8 => CacheKeyHash::Md5,
+        _ => CacheKeyHash::Fnv,
+    }
+}
+
+/// Directory of the cache entry for `url`, named by the configured cache
+/// key hash.  An entry still under its md5 name from before the hash was
+/// configurable is moved to the new name the first time it's looked up.
 pub fn url_cache_path(cache_dir: &std::path::Path, url: &str) -> std::path::PathBuf {
-    let mut hasher = md5::Context::new();
-    hasher.consume(url.as_bytes());
-    let digest = format!("{:x}", hasher.compute());
-    cache_dir.join(digest)
+    let hash = cache_key_hash();
+    let path = cache_dir.join(hash.key(url));
+    if hash != CacheKeyHash::Md5 && !path.exists() {
+        let legacy_path = cache_dir.join(CacheKeyHash::Md5.key(url));
+        if legacy_path.is_dir() {
+            debug!(
+                "Migrating cache entry for {} from {:?} to {:?}",
+                url,
+                legacy_path.to_str(),
+                path.to_str()
+            );
+            if let Err(e) = std::fs::rename(&legacy_path, &path) {
+                warn!(
+                    "Failed migrating cache entry {:?}, using it in place: {}",
+                    legacy_path.to_str(),
+                    e
+                );
+                return legacy_path;
+            }
+        }
+    }
+    path
 }
 
 pub fn url_metadata_cache_path(cache_dir: &std::path::Path, url: &str) -> std::path::PathBuf {
@@ -46,11 +125,288 @@ pub fn url_data_cache_path(cache_dir: &std::path::Path, url: &str) -> std::path:
     url_cache_path(cache_dir, url).join("data")
 }
 
+/// Entries under `cache_dir` holding only one of data and metadata, or a
+/// link to deduplicated data that is gone
+pub fn incomplete_entries(cache_dir: &std::path::Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut incomplete = Vec::new();
+    if !cache_dir.exists() {
+        return Ok(incomplete);
+    }
+    for entry in std::fs::read_dir(cache_dir)? {
+        let entry_path = entry?.path();
+        if !entry_path.is_dir() || entry_path.file_name() == Some("by-hash".as_ref()) {
+            continue;
+        }
+        if entry_path.join("data").exists() != entry_path.join("metadata").exists() {
+            incomplete.push(entry_path);
+        }
+    }
+    incomplete.sort();
+    Ok(incomplete)
+}
+
+/// Entries under `cache_dir` whose metadata can't be read back, such as
+/// metadata written by a version with a different schema
+pub fn corrupt_entries(cache_dir: &std::path::Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut corrupt = Vec::new();
+    if !cache_dir.exists() {
+        return Ok(corrupt);
+    }
+    for entry in std::fs::read_dir(cache_dir)? {
+        let entry_path = entry?.path();
+        let metadata_path = entry_path.join("metadata");
+        if !entry_path.is_dir() || !metadata_path.exists() {
+            continue;
+        }
+        if let Err(e) = RequestMetadata::try_from(metadata_path.as_path()) {
+            debug!(
+                "Unreadable cache metadata {:?}: {}",
+                metadata_path.to_str(),
+                e
+            );
+            corrupt.push(entry_path);
+        }
+    }
+    corrupt.sort();
+    Ok(corrupt)
+}
+
+/// How a cache entry's data is stored on disk
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+}
+
+impl Compression {
+    pub fn variants() -> Vec<String> {
+        vec!["gzip".to_string()]
+    }
+
+    /// File extension for data stored this way
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RequestMetadata {
+    /// Url the response came from, after any redirects
     source: String,
+    /// Url originally requested, which keys the cache entry
+    #[serde(default)]
+    requested_url: Option<String>,
     timestamp: DateTime<offset::Utc>,
     response_headers: HashMap<String, Vec<String>>,
+    /// Digests of the cached data computed while it was downloaded, keyed
+    /// by checksum type
+    #[serde(default)]
+    digests: HashMap<String, String>,
+    /// Modification time of the cached data when the digests were computed
+    #[serde(default)]
+    data_modified: Option<DateTime<offset::Utc>>,
+    /// Compression the data is stored with, none if it's stored as
+    /// downloaded
+    #[serde(default)]
+    compression: Option<Compression>,
+    /// Size of the data as downloaded, before any compression
+    #[serde(default)]
+    data_size: Option<u64>,
+}
+
+/// Data path of the entry for `url` if it was stored or last revalidated
+/// less than `ttl` ago, so it can be used without asking the server
+pub fn fresh_data_path(
+    cache_dir: &std::path::Path,
+    url: &str,
+    ttl: std::time::Duration,
+) -> Option<PathBuf> {
+    let metadata =
+        RequestMetadata::try_from(url_metadata_cache_path(cache_dir, url).as_path()).ok()?;
+    let data_path = url_data_cache_path(cache_dir, url);
+    if !data_path.exists() {
+        return None;
+    }
+    // A timestamp in the future gives no age, and isn't trusted
+    let age = offset::Utc::now()
+        .signed_duration_since(metadata.timestamp)
+        .to_std()
+        .ok()?;
+    if age >= ttl {
+        return None;
+    }
+    debug!(
+        "cache outcome=fresh url={} age={}s ttl={}s",
+        url,
+        age.as_secs(),
+        ttl.as_secs()
+    );
+    Some(data_path)
+}
+
+/// Look up the digest recorded when a cached file was downloaded.  `path`
+/// may be the cache data file or a link to it.  Only returns a digest if
+/// the data hasn't been modified since it was recorded.
+pub fn recorded_digest(path: &std::path::Path, checksum_type: &str) -> Option<String> {
+    let data_path = std::fs::canonicalize(path).ok()?;
+    if data_path.file_name()? != "data" {
+        return None;
+    }
+    let metadata =
+        RequestMetadata::try_from(data_path.with_file_name("metadata").as_path()).ok()?;
+    let modified: DateTime<offset::Utc> =
+        std::fs::metadata(&data_path).ok()?.modified().ok()?.into();
+    if metadata.data_modified != Some(modified) {
+        debug!(
+            "{:?} changed since its digest was recorded",
+            data_path.to_str()
+        );
+        return None;
+    }
+    metadata.digests.get(checksum_type).cloned()
+}
+
+/// Metadata of the cache entry whose data is at `path`, or is reached by
+/// following links from it.  Links are followed one at a time since a
+/// deduplicated entry's data is itself a link into the shared store.
+fn entry_metadata(path: &std::path::Path) -> Option<RequestMetadata> {
+    let mut path = path.to_path_buf();
+    // Same limit as the kernel's, in case of a link loop
+    for _ in 0..40 {
+        if path.file_name() == Some("data".as_ref()) {
+            let metadata_path = path.with_file_name("metadata");
+            if metadata_path.exists() {
+                return RequestMetadata::try_from(metadata_path.as_path()).ok();
+            }
+        }
+        let target = std::fs::read_link(&path).ok()?;
+        path = match path.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+    None
+}
+
+/// Compression of the cached data at `path`, which may be the cache data
+/// file or a link to it.  None for data stored as downloaded or files
+/// outside the cache.
+pub fn data_compression(path: &std::path::Path) -> Option<Compression> {
+    entry_metadata(path).and_then(|metadata| metadata.compression)
+}
+
+/// Size of the cached data at `path` once decompressed
+pub fn data_size(path: &std::path::Path) -> Result<u64> {
+    match entry_metadata(path) {
+        Some(RequestMetadata {
+            compression: Some(_),
+            data_size: Some(size),
+            ..
+        }) => Ok(size),
+        _ => Ok(std::fs::metadata(path)?.len()),
+    }
+}
+
+/// Read the cached data at `path`, decompressing it if it was stored
+/// compressed.  Files outside the cache are read as is.
+pub fn open_data(path: &std::path::Path) -> Result<Box<dyn Read>> {
+    let file = std::fs::File::open(path).map_err(Error::from)?;
+    match data_compression(path) {
+        Some(Compression::Gzip) => {
+            debug!("Decompressing cached {:?}", path.to_str());
+            Ok(Box::new(std::io::BufReader::new(
+                flate2::read::GzDecoder::new(file),
+            )))
+        }
+        None => Ok(Box::new(std::io::BufReader::new(file))),
+    }
+}
+
+/// Copy the cached data at `path` to `dest`, decompressing it if it was
+/// stored compressed.  Returns the number of bytes written.
+pub fn copy_data(path: &std::path::Path, dest: &std::path::Path) -> Result<u64> {
+    if data_compression(path).is_none() {
+        return std::fs::copy(path, dest).map_err(Error::from);
+    }
+    let mut out_file = std::io::BufWriter::new(std::fs::File::create(dest).map_err(Error::from)?);
+    let written = std::io::copy(&mut open_data(path)?, &mut out_file)?;
+    out_file.flush()?;
+    Ok(written)
+}
+
+/// Writer for cached data, compressing it on the way to disk if asked
+enum DataWriter {
+    Plain(std::io::BufWriter<std::fs::File>),
+    Gzip(GzEncoder<std::io::BufWriter<std::fs::File>>),
+}
+
+impl DataWriter {
+    fn create(path: &std::path::Path, compression: Option<Compression>) -> Result<Self> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path).map_err(Error::from)?);
+        Ok(match compression {
+            Some(Compression::Gzip) => {
+                DataWriter::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            None => DataWriter::Plain(file),
+        })
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            DataWriter::Plain(mut file) => file.flush(),
+            DataWriter::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl std::io::Write for DataWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            DataWriter::Plain(file) => file.write(buf),
+            DataWriter::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            DataWriter::Plain(file) => file.flush(),
+            DataWriter::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Writer adapter that computes an md5 digest of everything written through it
+struct Md5Writer<W: std::io::Write> {
+    inner: W,
+    hasher: md5::Context,
+}
+
+impl<W: std::io::Write> Md5Writer<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: md5::Context::new(),
+        }
+    }
+
+    /// The digest, and the inner writer back to finish off
+    fn finish(self) -> (String, W) {
+        (format!("{:x}", self.hasher.compute()), self.inner)
+    }
+}
+
+impl<W: std::io::Write> std::io::Write for Md5Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.consume(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 impl TryFrom<&std::path::Path> for RequestMetadata {
@@ -73,38 +429,58 @@ impl From<&reqwest::Response> for RequestMetadata {
         let mut headers_hm: HashMap<String, Vec<String>> = HashMap::new();
         for (key, value) in resp.headers() {
             let key_str = key.as_str();
-            let value_str = value.to_str().expect("invalid header value characters.");
-            let entry = headers_hm
-                .entry(key_str.to_string())
-                .or_insert_with(Vec::new);
+            // Header values aren't guaranteed to be ASCII, don't let an odd
+            // one from a CDN take down the whole download
+            let value_str = String::from_utf8_lossy(value.as_bytes());
+            if value.to_str().is_err() {
+                warn!(
+                    "Header {} has non-ASCII value, storing lossy copy {}",
+                    key_str, value_str
+                );
+            }
+            let entry = headers_hm.entry(key_str.to_string()).or_default();
             entry.push(value_str.to_string());
         }
         Self {
             source: resp.url().as_str().to_string(),
+            requested_url: None,
             timestamp: chrono::offset::Utc::now(),
             response_headers: headers_hm,
+            digests: HashMap::new(),
+            data_modified: None,
+            compression: None,
+            data_size: None,
         }
     }
 }
 
-impl Into<reqwest::header::HeaderMap> for RequestMetadata {
-    fn into(self) -> reqwest::header::HeaderMap {
+impl From<RequestMetadata> for reqwest::header::HeaderMap {
+    fn from(metadata: RequestMetadata) -> Self {
         debug!("Converting cache metadata into request headers...");
         let mut out = reqwest::header::HeaderMap::new();
-        if let Some(etags) = self.response_headers.get("etag") {
+        if let Some(etags) = metadata.response_headers.get("etag") {
             for etag in etags {
                 debug!("Request has etag {}", etag);
-                let headerval = reqwest::header::HeaderValue::from_str(etag)
-                    .expect("invalid header value characters in etag header.");
-                out.append(IF_NONE_MATCH, headerval);
+                match reqwest::header::HeaderValue::from_str(etag) {
+                    Ok(headerval) => {
+                        out.append(IF_NONE_MATCH, headerval);
+                    }
+                    Err(_) => warn!("Dropping etag with invalid characters: {}", etag),
+                }
             }
         }
-        if let Some(modifieds) = self.response_headers.get("last-modified") {
+        if let Some(modifieds) = metadata.response_headers.get("last-modified") {
             for modified in modifieds {
                 debug!("Request has modified date {}", modified);
-                let headerval = reqwest::header::HeaderValue::from_str(modified)
-                    .expect("invalid header value characters in last-modified header.");
-                out.append(IF_MODIFIED_SINCE, headerval);
+                match reqwest::header::HeaderValue::from_str(modified) {
+                    Ok(headerval) => {
+                        out.append(IF_MODIFIED_SINCE, headerval);
+                    }
+                    Err(_) => warn!(
+                        "Dropping last-modified date with invalid characters: {}",
+                        modified
+                    ),
+                }
             }
         }
         out
@@ -116,10 +492,87 @@ pub enum CacheType {
     Private,
 }
 
+/// Temporary path a cache file is written to before being moved into place
+fn partial_path(path: &std::path::Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
+
+/// Whether an IO error is the filesystem running out of space or quota
+fn is_disk_full(err: &std::io::Error) -> bool {
+    match err.raw_os_error() {
+        Some(code) => code == libc::ENOSPC || code == libc::EDQUOT,
+        None => false,
+    }
+}
+
+/// `Error::DiskFull` for `entry` if `err` is from running out of space,
+/// removing the partially written `partials`, otherwise `err` as is.  IO
+/// errors from writing a response body come wrapped in the http error.
+fn check_disk_full(err: Error, entry: &std::path::Path, partials: &[&std::path::Path]) -> Error {
+    let io_err = match &err {
+        Error::IoError(e) => Some(e),
+        Error::HttpError(e) => e.get_ref().and_then(|e| e.downcast_ref::<std::io::Error>()),
+        _ => None,
+    };
+    if !io_err.is_some_and(is_disk_full) {
+        return err;
+    }
+    for partial in partials {
+        if let Err(e) = std::fs::remove_file(partial) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed removing {}: {}", partial.display(), e);
+            }
+        }
+    }
+    Error::DiskFull {
+        path: entry.display().to_string(),
+    }
+}
+
+/// A directory only the current user can read, for a no-store response's
+/// body, removed along with its contents when dropped
+pub struct UncachedDir {
+    path: PathBuf,
+}
+
+impl UncachedDir {
+    fn create() -> Result<Self> {
+        use std::os::unix::fs::DirBuilderExt;
+
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "{}-{}-{}",
+                env!("CARGO_PKG_NAME"),
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            // Never reuse a directory someone else made
+            match std::fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(Error::from(e)),
+            }
+        }
+    }
+}
+
+impl Drop for UncachedDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            warn!("Failed removing {}: {}", self.path.display(), e);
+        }
+    }
+}
+
 pub struct CachedRequestBuilder {
     cache_type: CacheType,
     cache_dir: std::path::PathBuf,
     inner: reqwest::RequestBuilder,
+    no_cache: bool,
+    compress: bool,
 }
 
 impl CachedRequestBuilder {
@@ -132,15 +585,29 @@ impl CachedRequestBuilder {
             cache_type,
             cache_dir: cache_dir.to_path_buf(),
             inner: req_build,
+            no_cache: false,
+            compress: false,
         }
     }
 
+    /// Skip sending the cached validators so the server always returns the
+    /// full body.  The response is still written back to the cache.
+    pub fn no_cache(mut self, no_cache: bool) -> Self {
+        self.no_cache = no_cache;
+        self
+    }
+
+    /// Store the response body gzip compressed in the cache
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
     pub fn build(self) -> Result<CachedRequest> {
-        Ok(CachedRequest::new(
-            self.cache_type,
-            &self.cache_dir,
-            self.inner.build()?,
-        ))
+        let mut req = CachedRequest::new(self.cache_type, &self.cache_dir, self.inner.build()?);
+        req.no_cache = self.no_cache;
+        req.compress = self.compress;
+        Ok(req)
     }
 
     pub fn send(self, client: &reqwest::Client) -> Result<CachedResponse> {
@@ -161,6 +628,8 @@ pub struct CachedRequest {
     cache_type: CacheType,
     cache_dir: std::path::PathBuf,
     inner: reqwest::Request,
+    no_cache: bool,
+    compress: bool,
 }
 
 impl CachedRequest {
@@ -169,6 +638,8 @@ impl CachedRequest {
             cache_type,
             cache_dir: cache_dir.to_path_buf(),
             inner: req,
+            no_cache: false,
+            compress: false,
         }
     }
 
@@ -187,16 +658,37 @@ impl CachedRequest {
     pub fn send(mut self, client: &reqwest::Client) -> Result<CachedResponse> {
         // Load cache metadata and convert to headers requesting confirmation
         // that the cached data is valid
-        if self.url_metadata_cache_path().exists() {
-            let metadata: RequestMetadata =
-                RequestMetadata::try_from(self.url_metadata_cache_path().as_path())?;
-            let cache_request_headers: reqwest::header::HeaderMap = metadata.into();
-            self.inner
-                .headers_mut()
-                .extend(cache_request_headers.into_iter());
+        if self.no_cache {
+            debug!("Ignoring cached validators for {}", self.url());
+        } else if self.url_metadata_cache_path().exists() {
+            // Metadata that no longer parses is treated as a miss, the full
+            // response replaces it
+            match RequestMetadata::try_from(self.url_metadata_cache_path().as_path()) {
+                Ok(metadata) => {
+                    if let Some(requested_url) = &metadata.requested_url {
+                        if requested_url != self.url().as_str() {
+                            warn!(
+                                "Cache entry for {} was stored for {}",
+                                self.url(),
+                                requested_url
+                            );
+                        }
+                    }
+                    let cache_request_headers: reqwest::header::HeaderMap = metadata.into();
+                    self.inner.headers_mut().extend(cache_request_headers);
+                }
+                Err(Error::JsonError(e)) => warn!(
+                    "Ignoring unreadable cache metadata for {}, downloading it again: {}",
+                    self.url(),
+                    e
+                ),
+                Err(e) => return Err(e),
+            }
         }
 
         let builder = CachedResponseBuilder::new(self.cache_type, &self.cache_dir)
+            .request_url(self.inner.url().clone())
+            .compress(self.compress)
             .response(client.execute(self.inner)?);
         builder.build()
     }
@@ -213,7 +705,9 @@ impl std::ops::Deref for CachedRequest {
 pub struct CachedResponseBuilder {
     cache_type: CacheType,
     cache_dir: std::path::PathBuf,
+    request_url: Option<reqwest::Url>,
     response: Option<reqwest::Response>,
+    compress: bool,
 }
 
 impl CachedResponseBuilder {
@@ -221,22 +715,41 @@ impl CachedResponseBuilder {
         Self {
             cache_type,
             cache_dir: cache_dir.to_path_buf(),
+            request_url: None,
             response: None,
+            compress: false,
         }
     }
 
+    /// The url that was originally requested, used as the cache key.  Falls
+    /// back to the response url if unset.
+    pub fn request_url(mut self, url: reqwest::Url) -> Self {
+        self.request_url = Some(url);
+        self
+    }
+
+    /// Store the response body gzip compressed in the cache
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
     pub fn response(mut self, resp: reqwest::Response) -> Self {
         self.response = Some(resp);
         self
     }
 
     pub fn build(self) -> Result<CachedResponse> {
+        let response = self
+            .response
+            .expect("Cached response builder missing required parameter 'response'.");
         Ok(CachedResponse {
             cache_type: self.cache_type,
             cache_dir: self.cache_dir.clone(),
-            response: self
-                .response
-                .expect("Cached response builder missing required parameter 'response'."),
+            request_url: self.request_url.unwrap_or_else(|| response.url().clone()),
+            response,
+            compress: self.compress,
+            uncached: None,
         })
     }
 }
@@ -244,7 +757,10 @@ impl CachedResponseBuilder {
 pub struct CachedResponse {
     cache_type: CacheType,
     cache_dir: std::path::PathBuf,
+    request_url: reqwest::Url,
     response: reqwest::Response,
+    compress: bool,
+    uncached: Option<UncachedDir>,
 }
 
 impl std::ops::Deref for CachedResponse {
@@ -256,16 +772,20 @@ impl std::ops::Deref for CachedResponse {
 }
 
 impl CachedResponse {
+    pub fn request_url(&self) -> &reqwest::Url {
+        &self.request_url
+    }
+
     pub fn url_cache_path(&self) -> std::path::PathBuf {
-        url_cache_path(&self.cache_dir, self.url().as_str())
+        url_cache_path(&self.cache_dir, self.request_url.as_str())
     }
 
     pub fn url_data_cache_path(&self) -> std::path::PathBuf {
-        url_data_cache_path(&self.cache_dir, self.url().as_str())
+        url_data_cache_path(&self.cache_dir, self.request_url.as_str())
     }
 
     pub fn url_metadata_cache_path(&self) -> std::path::PathBuf {
-        url_metadata_cache_path(&self.cache_dir, self.url().as_str())
+        url_metadata_cache_path(&self.cache_dir, self.request_url.as_str())
     }
 
     pub fn cached_text(&mut self) -> Result<String> {
@@ -288,7 +808,7 @@ impl CachedResponse {
             .response
             .content_length()
             .map(|l| Vec::with_capacity(l as usize))
-            .unwrap_or_else(Vec::new);
+            .unwrap_or_default();
         let mut reader = self.cached_reader()?;
         reader.read_to_end(&mut bytes)?;
         let (text, _, _) = encoding.decode(&bytes);
@@ -303,22 +823,51 @@ impl CachedResponse {
             .response
             .content_length()
             .map(|l| Vec::with_capacity(l as usize))
-            .unwrap_or_else(Vec::new);
+            .unwrap_or_default();
         let mut reader = self.cached_reader()?;
         reader.read_to_end(&mut bytes)?;
         serde_json::from_slice(&bytes).map_err(Error::from)
     }
 
-    pub fn cached_copy_to<W: ?Sized>(&mut self, w: &mut W) -> Result<u64>
+    /// Like `cached_json`, but deserializes straight from the cached file
+    /// rather than reading all of it into memory first, for large responses
+    pub fn cached_json_from_reader<T: DeserializeOwned>(&mut self) -> Result<T> {
+        serde_json::from_reader(self.cached_reader()?).map_err(Error::from)
+    }
+
+    pub fn cached_copy_to<W>(&mut self, w: &mut W) -> Result<u64>
     where
-        W: std::io::Write,
+        W: std::io::Write + ?Sized,
     {
         std::io::copy(&mut self.cached_reader()?, w).map_err(Error::from)
     }
 
-    fn update_cache(&mut self) -> Result<()> {
+    /// Error if the body is still encoded, which happens when a server
+    /// sends an encoding the client can't decode.  Caching it would store
+    /// bytes nothing can read back.
+    fn check_content_encoding(&self) -> Result<()> {
+        match self
+            .response
+            .headers()
+            .get(CONTENT_ENCODING)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        {
+            Some(encoding) if !encoding.trim().eq_ignore_ascii_case("identity") => {
+                Err(Error::UnsupportedContentEncoding {
+                    url: self.url().to_string(),
+                    encoding,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Store the response body in the cache, returning its size
+    fn update_cache(&mut self) -> Result<u64> {
+        self.check_content_encoding()?;
+
         // Ensure a cache directory exists
-        std::fs::create_dir_all(&self.url_cache_path())?;
+        cache::create_cache_dir_all(&self.url_cache_path())?;
 
         // Write data to cache
         debug!(
@@ -326,11 +875,50 @@ impl CachedResponse {
             self.url(),
             self.url_data_cache_path().to_str()
         );
-        let mut out_file = std::io::BufWriter::new(
-            std::fs::File::create(self.url_data_cache_path()).map_err(Error::from)?,
-        );
+        // Write into temporary files beside the entry and rename them into
+        // place once complete, so an interrupted write never leaves a
+        // truncated entry behind.  Renaming also replaces a link into a
+        // shared, deduplicated blob rather than writing through it.
+        let partial_data_path = partial_path(&self.url_data_cache_path());
+        let partial_metadata_path = partial_path(&self.url_metadata_cache_path());
+        let _partial_data = PartialFile::track(&partial_data_path);
+        let _partial_metadata = PartialFile::track(&partial_metadata_path);
+
+        // Hash the data on its way to disk so verifying it later doesn't
+        // need another full pass over the file.  The hash is of the data as
+        // downloaded, ahead of any compression.
+        let compression = if self.compress {
+            Some(Compression::Gzip)
+        } else {
+            None
+        };
+        let entry_path = self.url_data_cache_path();
+        let disk_full = |err: Error| {
+            check_disk_full(
+                err,
+                &entry_path,
+                &[&partial_data_path, &partial_metadata_path],
+            )
+        };
+        let mut out_file =
+            Md5Writer::new(DataWriter::create(&partial_data_path, compression).map_err(disk_full)?);
 
-        self.response.copy_to(&mut out_file).map_err(Error::from)?;
+        let written = self
+            .response
+            .copy_to(&mut out_file)
+            .map_err(|e| disk_full(Error::from(e)))?;
+        let (digest, data_file) = out_file.finish();
+        data_file.finish().map_err(|e| disk_full(Error::from(e)))?;
+        if let Some(expected) = self.response.content_length() {
+            if written != expected {
+                std::fs::remove_file(&partial_data_path)?;
+                return Err(Error::IncompleteDownload {
+                    url: self.url().to_string(),
+                    expected,
+                    actual: written,
+                });
+            }
+        }
 
         // Write metadata to cache
         debug!(
@@ -338,11 +926,111 @@ impl CachedResponse {
             self.url(),
             self.url_metadata_cache_path().to_str()
         );
-        let req_metadata = RequestMetadata::from(&self.response);
-        let mut out_file = std::io::BufWriter::new(
-            std::fs::File::create(self.url_metadata_cache_path()).map_err(Error::from)?,
+        let mut req_metadata = RequestMetadata::from(&self.response);
+        req_metadata.requested_url = Some(self.request_url.to_string());
+        if req_metadata.source != self.request_url.as_str() {
+            debug!(
+                "{} was redirected to {}",
+                self.request_url, req_metadata.source
+            );
+        }
+        req_metadata.digests.insert("md5".to_string(), digest);
+        req_metadata.compression = compression;
+        req_metadata.data_size = Some(written);
+        req_metadata.data_modified =
+            Some(std::fs::metadata(&partial_data_path)?.modified()?.into());
+        let metadata_json = serde_json::to_vec_pretty(&req_metadata).map_err(Error::from)?;
+        std::fs::write(&partial_metadata_path, metadata_json)
+            .map_err(|e| disk_full(Error::from(e)))?;
+
+        // Drop the old metadata first so a crash between the renames leaves
+        // data without validators, which is simply fetched again
+        if self.url_metadata_cache_path().exists() {
+            std::fs::remove_file(self.url_metadata_cache_path())?;
+        }
+        std::fs::rename(&partial_data_path, self.url_data_cache_path())?;
+        std::fs::rename(&partial_metadata_path, self.url_metadata_cache_path())?;
+        cache::apply_cache_mode(&self.url_data_cache_path())?;
+        cache::apply_cache_mode(&self.url_metadata_cache_path())?;
+        Ok(written)
+    }
+
+    /// Whether the server forbids storing this response
+    pub fn is_no_store(&self) -> bool {
+        self.response
+            .headers()
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+    }
+
+    /// Record that the server just confirmed the cached entry is current
+    fn freshen_metadata(&self) -> Result<()> {
+        let metadata_path = self.url_metadata_cache_path();
+        let mut metadata = RequestMetadata::try_from(metadata_path.as_path())?;
+        metadata.timestamp = offset::Utc::now();
+        let partial_metadata_path = partial_path(&metadata_path);
+        let _partial_metadata = PartialFile::track(&partial_metadata_path);
+        std::fs::write(
+            &partial_metadata_path,
+            serde_json::to_vec_pretty(&metadata)?,
+        )?;
+        std::fs::rename(&partial_metadata_path, &metadata_path)?;
+        cache::apply_cache_mode(&metadata_path)
+    }
+
+    fn remove_cache_entry(&self) -> Result<()> {
+        // Drop anything cached before the server started sending no-store
+        if self.url_cache_path().exists() {
+            debug!(
+                "Removing cache entry {:?} for no-store response",
+                self.url_cache_path().to_str()
+            );
+            std::fs::remove_dir_all(self.url_cache_path())?;
+        }
+        Ok(())
+    }
+
+    fn write_uncached(&mut self) -> Result<PathBuf> {
+        self.remove_cache_entry()?;
+        self.check_content_encoding()?;
+        let tmp_dir = UncachedDir::create()?;
+        let tmp_path = tmp_dir.path.join("data");
+        debug!("Writing no-store response to {:?}", tmp_path.to_str());
+        let _partial = PartialFile::track(&tmp_path);
+        let out_file = {
+            use std::os::unix::fs::OpenOptionsExt;
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&tmp_path)
+                .map_err(Error::from)?
+        };
+        // Removed when the response, or whoever takes the dir, is dropped
+        self.uncached = Some(tmp_dir);
+        let mut out_file = std::io::BufWriter::new(out_file);
+        let written = self.response.copy_to(&mut out_file).map_err(Error::from)?;
+        debug!(
+            "cache outcome=uncached url={} bytes={}",
+            self.request_url, written
         );
-        serde_json::to_writer_pretty(&mut out_file, &req_metadata).map_err(Error::from)
+        Ok(tmp_path)
+    }
+
+    /// The private directory holding a no-store body written by
+    /// `cached_file_path`, which keeps the file around for as long as it is
+    /// held
+    pub fn take_uncached(&mut self) -> Option<UncachedDir> {
+        self.uncached.take()
+    }
+
+    /// Whether the server confirmed the cached copy is current, so the body
+    /// wasn't downloaded again
+    pub fn served_from_cache(&self) -> bool {
+        self.response.status() == StatusCode::NOT_MODIFIED
     }
 
     pub fn cached_file_path(&mut self) -> Result<PathBuf> {
@@ -351,25 +1039,58 @@ impl CachedResponse {
         let status = self.response.status();
 
         // New data for us
-        if status.is_success() {
+        if status.is_success() && self.is_no_store() {
+            info!(
+                "Downloading {} outside the cache (no-store)...",
+                self.response.url()
+            );
+            return self.write_uncached();
+        } else if status.is_success() {
             info!("Downloading {} into the cache...", self.response.url());
-            self.update_cache()?;
+            let written = self.update_cache()?;
+            debug!(
+                "cache outcome=downloaded url={} bytes={}",
+                self.request_url, written
+            );
         } else if status == StatusCode::NOT_MODIFIED {
             // cached data is valid, use that
             info!("Using cached copy of {}", self.response.url());
+            if let Err(e) = self.freshen_metadata() {
+                warn!(
+                    "Failed recording the revalidation of {}: {}",
+                    self.request_url, e
+                );
+            }
+            // Only logged, so an unreadable size doesn't fail the request
+            let bytes = data_size(&self.url_data_cache_path())
+                .map(|size| size.to_string())
+                .unwrap_or_else(|_| "unknown".to_string());
+            debug!(
+                "cache outcome=revalidated url={} bytes={}",
+                self.request_url, bytes
+            );
         } else {
             // Some kind of error occurred, for which we can't tell
             // if the cache is valid or not
-            return Err(Error::from(status));
+            return Err(Error::HttpStatus {
+                url: self.request_url.to_string(),
+                status,
+            });
         }
 
         // There should now be a locally cached version of the requested url
         Ok(self.url_data_cache_path())
     }
 
-    pub fn cached_reader(&mut self) -> Result<impl Read> {
-        Ok(std::io::BufReader::new(
-            std::fs::File::open(self.cached_file_path()?).map_err(Error::from)?,
-        ))
+    pub fn cached_reader(&mut self) -> Result<Box<dyn Read>> {
+        if self.response.status().is_success() && self.is_no_store() {
+            // Keep no-store responses off the disk entirely
+            self.remove_cache_entry()?;
+            self.check_content_encoding()?;
+            let mut bytes = Vec::new();
+            self.response.read_to_end(&mut bytes)?;
+            return Ok(Box::new(std::io::Cursor::new(bytes)));
+        }
+        open_data(&self.cached_file_path()?)
     }
 }
@@ -0,0 +1,185 @@
+// Needed to bring in BufRead trait
+use std::io::BufRead;
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use chrono::{offset, DateTime};
+use lazy_static::lazy_static;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::caching_client;
+use crate::error::{Error, Result};
+use crate::output;
+
+/// Computes the digest of a file, formatted the way the L3 repo records it
+pub type Verifier = fn(&Path) -> Result<String>;
+
+lazy_static! {
+    static ref VERIFIERS: RwLock<HashMap<String, Verifier>> = {
+        let mut verifiers: HashMap<String, Verifier> = HashMap::new();
+        verifiers.insert("md5".to_string(), md5_digest);
+        verifiers.insert("crc32".to_string(), crc32_digest);
+        RwLock::new(verifiers)
+    };
+}
+
+/// Add or replace the verifier used for a checksum type
+pub fn register(checksum_type: &str, verifier: Verifier) {
+    VERIFIERS
+        .write()
+        .expect("checksum registry lock poisoned")
+        .insert(checksum_type.to_string(), verifier);
+}
+
+/// Checksum types with a registered verifier, sorted
+pub fn supported_types() -> Vec<String> {
+    let mut types: Vec<String> = VERIFIERS
+        .read()
+        .expect("checksum registry lock poisoned")
+        .keys()
+        .cloned()
+        .collect();
+    types.sort();
+    types
+}
+
+/// Compute the digest of a file with the verifier registered for
+/// `checksum_type`
+pub fn compute_digest(filename: &Path, checksum_type: &str) -> Result<String> {
+    let verifier = VERIFIERS
+        .read()
+        .expect("checksum registry lock poisoned")
+        .get(checksum_type)
+        .copied()
+        .ok_or_else(|| Error::UnsupportedChecksumType(checksum_type.to_owned()))?;
+    verifier(filename)
+}
+
+/// Whether two hex digests are the same, ignoring case and surrounding
+/// whitespace, which repos aren't consistent about
+pub fn digests_match(actual: &str, expected: &str) -> bool {
+    actual.trim().eq_ignore_ascii_case(expected.trim())
+}
+
+/// Feed the contents of a file through `update` a buffer at a time, showing
+/// progress as it goes.  Data stored compressed in the http cache is
+/// decompressed first, so it hashes the same as the package downloaded.
+pub fn stream_file<F: FnMut(&[u8])>(filename: &Path, mut update: F) -> Result<()> {
+    let file_len = caching_client::data_size(filename)?;
+    let mut in_file = std::io::BufReader::new(caching_client::open_data(filename)?);
+    let pbar = output::progress_bar(file_len);
+    while !in_file.fill_buf().map_err(Error::from)?.is_empty() {
+        let buf_len = in_file.buffer().len();
+        debug!("Updating checksum from {} bytes...", buf_len);
+        update(in_file.buffer());
+        in_file.consume(buf_len);
+        pbar.inc(buf_len.try_into().unwrap());
+    }
+    Ok(())
+}
+
+fn md5_digest(filename: &Path) -> Result<String> {
+    let mut hasher = md5::Context::new();
+    stream_file(filename, |buf| hasher.consume(buf))?;
+    Ok(format!("{:x}", hasher.compute()))
+}
+
+fn crc32_digest(filename: &Path) -> Result<String> {
+    let mut hasher = crc32fast::Hasher::new();
+    stream_file(filename, |buf| hasher.update(buf))?;
+    Ok(format!("{:08x}", hasher.finalize()))
+}
+
+/// Sidecar in a package directory remembering the digests verify computed
+pub const DIGEST_CACHE_FILE: &str = ".verified.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct DigestCacheEntry {
+    size: u64,
+    modified: DateTime<offset::Utc>,
+    checksum_type: String,
+    digest: String,
+}
+
+/// Digests of files keyed by path, trusted for as long as the file's size
+/// and modification time are unchanged
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DigestCache {
+    #[serde(skip)]
+    path: PathBuf,
+    files: HashMap<String, DigestCacheEntry>,
+}
+
+impl DigestCache {
+    /// An empty cache that will be saved in `dir`
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            path: dir.join(DIGEST_CACHE_FILE),
+            files: HashMap::new(),
+        }
+    }
+
+    /// The cache saved in `dir`, or an empty one if there is none or it
+    /// can't be read
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(DIGEST_CACHE_FILE);
+        let loaded = std::fs::File::open(&path)
+            .map_err(Error::from)
+            .and_then(|file| {
+                serde_json::from_reader::<_, Self>(std::io::BufReader::new(file))
+                    .map_err(Error::from)
+            });
+        match loaded {
+            Ok(cache) => Self { path, ..cache },
+            Err(e) => {
+                debug!("Not using digest cache {:?}: {}", path.to_str(), e);
+                Self::new(dir)
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        serde_json::to_writer(
+            std::io::BufWriter::new(std::fs::File::create(&self.path)?),
+            self,
+        )
+        .map_err(Error::from)
+    }
+
+    /// The `checksum_type` digest of `filename`, from the cache if the file
+    /// is unchanged since it was recorded, else from `compute`
+    pub fn digest<F>(&mut self, filename: &Path, checksum_type: &str, compute: F) -> Result<String>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        let file_meta = std::fs::metadata(filename)?;
+        let size = file_meta.len();
+        let modified: DateTime<offset::Utc> = file_meta.modified()?.into();
+        let key = filename.to_string_lossy().to_string();
+        if let Some(entry) = self.files.get(&key) {
+            if entry.size == size
+                && entry.modified == modified
+                && entry.checksum_type == checksum_type
+            {
+                debug!("Using cached checksum for unchanged {:?}", filename);
+                return Ok(entry.digest.clone());
+            }
+        }
+        let digest = compute()?;
+        self.files.insert(
+            key,
+            DigestCacheEntry {
+                size,
+                modified,
+                checksum_type: checksum_type.to_string(),
+                digest: digest.clone(),
+            },
+        );
+        Ok(digest)
+    }
+}
@@ -1,5 +1,6 @@
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Error {
     NumParseError(std::num::ParseIntError),
@@ -7,27 +8,89 @@ pub enum Error {
     LogError(flexi_logger::FlexiLoggerError),
     IoError(std::io::Error),
     JsonError(serde_json::error::Error),
+    YamlError(serde_yaml::Error),
     HttpError(reqwest::Error),
-    HttpStatusError(http::status::StatusCode),
+    TlsError(openssl::error::ErrorStack),
+    IncompleteDownload {
+        url: String,
+        expected: u64,
+        actual: u64,
+    },
+    UnsupportedContentEncoding {
+        url: String,
+        encoding: String,
+    },
+    HttpStatus {
+        url: String,
+        status: http::status::StatusCode,
+    },
     InvalidUrl(url::ParseError),
+    InvalidHeaderValue(String),
+    MalformedHeader(String),
     MissingProductCategory(Vec<String>),
     InvalidProductCategory(String, Vec<String>),
     MissingTargetOS(Vec<String>),
     InvalidTargetOS(String, Vec<String>),
     MissingRelease(Vec<String>),
     InvalidRelease(String, Vec<String>),
+    SingleReleaseOnly(String),
+    InvalidFormat(String, Vec<String>),
+    InvalidLogFormat(String, Vec<String>),
+    InvalidListKind(String, Vec<String>),
+    InvalidLayout(String, Vec<String>),
+    InvalidSort(String, Vec<String>),
+    InvalidExportFormat(String, Vec<String>),
+    InvalidProgressMode(String, Vec<String>),
+    InvalidCacheKeyHash(String, Vec<String>),
+    InvalidResolve(String),
+    NoMatchingVersion {
+        component: String,
+        version: String,
+        available: Vec<String>,
+    },
+    UnsupportedSchemaVersion(String, String),
     L2RepoReleaseMissingUrl(String),
+    EmptyRepo(String),
     InvalidSection(String),
     InvalidGroup(String),
     InvalidComponent(String),
+    HiddenComponent(String),
     UnsupportedChecksumType(String),
     FileNotExist(String),
+    PackagesFailed {
+        action: String,
+        succeeded: usize,
+        failed: usize,
+    },
     FileDigestInvalid {
         file: String,
         cktype: String,
         expected: String,
         actual: String,
     },
+    FileSizeInvalid {
+        file: String,
+        expected: u64,
+        actual: u64,
+    },
+    InsufficientDiskSpace {
+        path: String,
+        available: u64,
+        required: u64,
+    },
+    DiskFull {
+        path: String,
+    },
+    ChecksFailed {
+        passed: usize,
+        failed: usize,
+    },
+    LockfileDrift(String),
+    UnsafeFileName(String),
+    StdinConflict(String),
+    NoComponentsListed(String),
+    /// What was being done when the wrapped error happened
+    Context(String, Box<Error>),
 }
 
 impl From<std::num::ParseIntError> for Error {
@@ -60,15 +123,21 @@ impl From<serde_json::error::Error> for Error {
     }
 }
 
+impl From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Self {
+        Error::YamlError(err)
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
         Error::HttpError(err)
     }
 }
 
-impl From<http::status::StatusCode> for Error {
-    fn from(err: http::status::StatusCode) -> Self {
-        Error::HttpStatusError(err)
+impl From<openssl::error::ErrorStack> for Error {
+    fn from(err: openssl::error::ErrorStack) -> Self {
+        Error::TlsError(err)
     }
 }
 
@@ -78,21 +147,177 @@ impl From<url::ParseError> for Error {
     }
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Error {
+    /// Stable short code identifying the kind of error, for tooling that
+    /// matches on output.  An error with context has the code of the error
+    /// it wraps.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Context(_, source) => source.code(),
+            Error::NumParseError(_) => "E_NUM_PARSE",
+            Error::Utf8ParseError(_) => "E_UTF8",
+            Error::LogError(_) => "E_LOG",
+            Error::IoError(_) => "E_IO",
+            Error::JsonError(_) => "E_JSON",
+            Error::YamlError(_) => "E_YAML",
+            Error::HttpError(_) => "E_HTTP",
+            Error::TlsError(_) => "E_TLS",
+            Error::IncompleteDownload { .. } => "E_INCOMPLETE_DOWNLOAD",
+            Error::UnsupportedContentEncoding { .. } => "E_CONTENT_ENCODING",
+            Error::HttpStatus { .. } => "E_HTTP_STATUS",
+            Error::InvalidUrl(_) => "E_INVALID_URL",
+            Error::InvalidHeaderValue(_) => "E_INVALID_HEADER",
+            Error::MalformedHeader(_) => "E_MALFORMED_HEADER",
+            Error::MissingProductCategory(_) => "E_MISSING_PRODUCT_CATEGORY",
+            Error::InvalidProductCategory(..) => "E_INVALID_PRODUCT_CATEGORY",
+            Error::MissingTargetOS(_) => "E_MISSING_TARGET_OS",
+            Error::InvalidTargetOS(..) => "E_INVALID_TARGET_OS",
+            Error::MissingRelease(_) => "E_MISSING_RELEASE",
+            Error::InvalidRelease(..) => "E_INVALID_RELEASE",
+            Error::InvalidFormat(..) => "E_INVALID_FORMAT",
+            Error::InvalidLogFormat(..) => "E_INVALID_LOG_FORMAT",
+            Error::InvalidListKind(..) => "E_INVALID_LIST_KIND",
+            Error::InvalidLayout(..) => "E_INVALID_LAYOUT",
+            Error::InvalidSort(..) => "E_INVALID_SORT",
+            Error::InvalidExportFormat(..) => "E_INVALID_EXPORT_FORMAT",
+            Error::InvalidProgressMode(..) => "E_INVALID_PROGRESS_MODE",
+            Error::InvalidCacheKeyHash(..) => "E_INVALID_CACHE_KEY_HASH",
+            Error::InvalidResolve(_) => "E_INVALID_RESOLVE",
+            Error::NoMatchingVersion { .. } => "E_NO_MATCHING_VERSION",
+            Error::UnsupportedSchemaVersion(..) => "E_SCHEMA_VERSION",
+            Error::L2RepoReleaseMissingUrl(_) => "E_RELEASE_URL",
+            Error::EmptyRepo(_) => "E_EMPTY_REPO",
+            Error::InvalidSection(_) => "E_INVALID_SECTION",
+            Error::InvalidGroup(_) => "E_INVALID_GROUP",
+            Error::InvalidComponent(_) => "E_INVALID_COMPONENT",
+            Error::HiddenComponent(_) => "E_HIDDEN_COMPONENT",
+            Error::UnsupportedChecksumType(_) => "E_CHECKSUM_TYPE",
+            Error::FileNotExist(_) => "E_FILE_MISSING",
+            Error::PackagesFailed { .. } => "E_PACKAGES_FAILED",
+            Error::FileDigestInvalid { .. } => "E_FILE_DIGEST",
+            Error::FileSizeInvalid { .. } => "E_FILE_SIZE",
+            Error::InsufficientDiskSpace { .. } => "E_DISK_SPACE",
+            Error::DiskFull { .. } => "E_DISK_FULL",
+            Error::ChecksFailed { .. } => "E_CHECKS_FAILED",
+            Error::SingleReleaseOnly(_) => "E_SINGLE_RELEASE_ONLY",
+            Error::LockfileDrift(_) => "E_LOCKFILE_DRIFT",
+            Error::UnsafeFileName(_) => "E_UNSAFE_FILE_NAME",
+            Error::StdinConflict(_) => "E_STDIN_CONFLICT",
+            Error::NoComponentsListed(_) => "E_NO_COMPONENTS_LISTED",
+        }
+    }
+
+    /// Process exit code for the error, grouping the codes from `code()`:
+    ///
+    /// * 2 - invalid selections or option values (`E_INVALID_*`,
+    ///   `E_MISSING_*`, `E_HIDDEN_COMPONENT`, `E_INVALID_HEADER`,
+    ///   `E_MALFORMED_HEADER`,
+    ///   `E_NO_MATCHING_VERSION`, `E_SINGLE_RELEASE_ONLY`,
+    ///   `E_STDIN_CONFLICT`, `E_NO_COMPONENTS_LISTED`)
+    /// * 3 - network failures (`E_HTTP`, `E_HTTP_STATUS`,
+    ///   `E_INCOMPLETE_DOWNLOAD`, `E_CONTENT_ENCODING`)
+    /// * 4 - checksum and verification failures (`E_FILE_DIGEST`,
+    ///   `E_FILE_SIZE`, `E_FILE_MISSING`, `E_CHECKSUM_TYPE`,
+    ///   `E_PACKAGES_FAILED`, `E_LOCKFILE_DRIFT`, `E_UNSAFE_FILE_NAME`)
+    /// * 5 - local IO failures (`E_IO`, `E_DISK_SPACE`, `E_DISK_FULL`)
+    /// * 1 - anything else
+    pub fn exit_code(&self) -> i32 {
         match self {
-            Error::NumParseError(e) => write!(f, "ERROR: {}", e),
-            Error::Utf8ParseError(e) => write!(f, "ERROR: {}", e),
-            Error::LogError(e) => write!(f, "ERROR: {}", e),
-            Error::IoError(e) => write!(f, "ERROR: {}", e),
-            Error::JsonError(e) => write!(f, "ERROR: {}", e),
-            Error::HttpError(e) => write!(f, "ERROR: {}", e),
-            Error::HttpStatusError(e) => write!(f, "ERROR: {}", e),
-            Error::InvalidUrl(e) => write!(f, "ERROR: {}", e),
+            Error::Context(_, source) => source.exit_code(),
+            Error::InvalidHeaderValue(_)
+            | Error::MalformedHeader(_)
+            | Error::MissingProductCategory(_)
+            | Error::InvalidProductCategory(..)
+            | Error::MissingTargetOS(_)
+            | Error::InvalidTargetOS(..)
+            | Error::MissingRelease(_)
+            | Error::InvalidRelease(..)
+            | Error::SingleReleaseOnly(_)
+            | Error::InvalidFormat(..)
+            | Error::InvalidLogFormat(..)
+            | Error::InvalidListKind(..)
+            | Error::InvalidLayout(..)
+            | Error::InvalidSort(..)
+            | Error::InvalidExportFormat(..)
+            | Error::InvalidProgressMode(..)
+            | Error::InvalidCacheKeyHash(..)
+            | Error::InvalidResolve(_)
+            | Error::NoMatchingVersion { .. }
+            | Error::InvalidSection(_)
+            | Error::InvalidGroup(_)
+            | Error::InvalidComponent(_)
+            | Error::HiddenComponent(_)
+            | Error::StdinConflict(_)
+            | Error::NoComponentsListed(_) => 2,
+            Error::HttpError(_)
+            | Error::HttpStatus { .. }
+            | Error::IncompleteDownload { .. }
+            | Error::UnsupportedContentEncoding { .. } => 3,
+            Error::FileDigestInvalid { .. }
+            | Error::FileSizeInvalid { .. }
+            | Error::FileNotExist(_)
+            | Error::UnsupportedChecksumType(_)
+            | Error::PackagesFailed { .. }
+            | Error::LockfileDrift(_)
+            | Error::UnsafeFileName(_) => 4,
+            Error::IoError(_) | Error::InsufficientDiskSpace { .. } | Error::DiskFull { .. } => 5,
+            _ => 1,
+        }
+    }
+}
+
+impl Error {
+    /// The error with any context taken off
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context(_, source) => source.root(),
+            _ => self,
+        }
+    }
+
+    /// The message, after the contexts from the outermost in
+    fn fmt_message(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Context(context, source) => {
+                write!(f, "{}: ", context)?;
+                source.fmt_message(f)
+            }
+            Error::NumParseError(e) => write!(f, "{}", e),
+            Error::Utf8ParseError(e) => write!(f, "{}", e),
+            Error::LogError(e) => write!(f, "{}", e),
+            Error::IoError(e) => write!(f, "{}", e),
+            Error::JsonError(e) => write!(f, "{}", e),
+            Error::YamlError(e) => write!(f, "{}", e),
+            Error::HttpError(e) => write!(f, "{}", e),
+            Error::TlsError(e) => write!(f, "{}", e),
+            Error::IncompleteDownload {
+                url,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Download of {} ended after {} of {} bytes",
+                url, actual, expected
+            ),
+            Error::UnsupportedContentEncoding { url, encoding } => write!(
+                f,
+                "Response for {} uses unsupported content encoding {}",
+                url, encoding
+            ),
+            Error::HttpStatus { url, status } => {
+                write!(f, "Request for {} failed with {}", url, status)
+            }
+            Error::InvalidUrl(e) => write!(f, "{}", e),
+            Error::InvalidHeaderValue(v) => write!(f, "Invalid HTTP header value {}", v),
+            Error::MalformedHeader(h) => write!(
+                f,
+                "Malformed header {:?}, expected \"Name: Value\" with a valid name and value",
+                h
+            ),
             Error::MissingProductCategory(cats) => {
                 write!(
                     f,
-                    "ERROR: Required option --product-category is missing. Legal values:"
+                    "Required option --product-category is missing. Legal values:"
                 )?;
                 for cat in cats {
                     write!(f, "\t{}", cat)?;
@@ -102,7 +327,7 @@ impl std::fmt::Display for Error {
             Error::InvalidProductCategory(e, cats) => {
                 write!(
                     f,
-                    "ERROR: Invalid value {} for option --product-category. Legal values:",
+                    "Invalid value {} for option --product-category. Legal values:",
                     e
                 )?;
                 for cat in cats {
@@ -113,7 +338,7 @@ impl std::fmt::Display for Error {
             Error::MissingTargetOS(tgts) => {
                 write!(
                     f,
-                    "ERROR: Required option --target-os is missing. Legal values:"
+                    "Required option --target-os is missing. Legal values:"
                 )?;
                 for tgt in tgts {
                     write!(f, "\t{}", tgt)?;
@@ -123,7 +348,7 @@ impl std::fmt::Display for Error {
             Error::InvalidTargetOS(e, tgts) => {
                 write!(
                     f,
-                    "ERROR: Invalid value {} for option --target-os. Legal values:",
+                    "Invalid value {} for option --target-os. Legal values:",
                     e
                 )?;
                 for tgt in tgts {
@@ -134,7 +359,7 @@ impl std::fmt::Display for Error {
             Error::MissingRelease(rels) => {
                 write!(
                     f,
-                    "ERROR: Required option --release is missing. Legal values:"
+                    "Required option --release is missing. Legal values:"
                 )?;
                 for rel in rels {
                     write!(f, "\t{}", rel)?;
@@ -144,7 +369,7 @@ impl std::fmt::Display for Error {
             Error::InvalidRelease(e, rels) => {
                 write!(
                     f,
-                    "ERROR: Invalid value {} for option --release. Legal values:",
+                    "Invalid value {} for option --release. Legal values:",
                     e
                 )?;
                 for rel in rels {
@@ -152,22 +377,156 @@ impl std::fmt::Display for Error {
                 }
                 write!(f, "")
             }
+            Error::InvalidFormat(e, fmts) => {
+                write!(
+                    f,
+                    "Invalid value {} for option --format. Legal values:",
+                    e
+                )?;
+                for fmt in fmts {
+                    write!(f, "\t{}", fmt)?;
+                }
+                write!(f, "")
+            }
+            Error::InvalidLogFormat(e, fmts) => {
+                write!(
+                    f,
+                    "Invalid value {} for option --log-format. Legal values:",
+                    e
+                )?;
+                for fmt in fmts {
+                    write!(f, "\t{}", fmt)?;
+                }
+                write!(f, "")
+            }
+            Error::InvalidListKind(e, kinds) => {
+                write!(f, "Invalid value {} to list. Legal values:", e)?;
+                for kind in kinds {
+                    write!(f, "\t{}", kind)?;
+                }
+                write!(f, "")
+            }
+            Error::InvalidLayout(e, layouts) => {
+                write!(
+                    f,
+                    "Invalid value {} for option --layout. Legal values:",
+                    e
+                )?;
+                for layout in layouts {
+                    write!(f, "\t{}", layout)?;
+                }
+                write!(f, "")
+            }
+            Error::InvalidSort(e, sorts) => {
+                write!(
+                    f,
+                    "Invalid value {} for option --sort. Legal values:",
+                    e
+                )?;
+                for sort in sorts {
+                    write!(f, "\t{}", sort)?;
+                }
+                write!(f, "")
+            }
+            Error::InvalidExportFormat(e, formats) => {
+                write!(f, "Invalid value {} to export as. Legal values:", e)?;
+                for format in formats {
+                    write!(f, "\t{}", format)?;
+                }
+                write!(f, "")
+            }
+            Error::InvalidProgressMode(e, modes) => {
+                write!(
+                    f,
+                    "Invalid value {} for option --progress. Legal values:",
+                    e
+                )?;
+                for mode in modes {
+                    write!(f, "\t{}", mode)?;
+                }
+                write!(f, "")
+            }
+            Error::InvalidResolve(value) => write!(
+                f,
+                "Invalid value {} for option --resolve, expected <host>:<port>:<address>",
+                value
+            ),
+            Error::InvalidCacheKeyHash(e, hashes) => {
+                write!(
+                    f,
+                    "Invalid value {} for option --cache-key-hash. Legal values:",
+                    e
+                )?;
+                for hash in hashes {
+                    write!(f, "\t{}", hash)?;
+                }
+                write!(f, "")
+            }
+            Error::NoMatchingVersion {
+                component,
+                version,
+                available,
+            } => {
+                write!(
+                    f,
+                    "No version of {} matches {}. Available versions:",
+                    component, version
+                )?;
+                for version in available {
+                    write!(f, "\t{}", version)?;
+                }
+                write!(f, "")
+            }
+            Error::UnsupportedSchemaVersion(found, supported) => write!(
+                f,
+                "Repo schema version {} is newer than the supported {}, update {} or drop --strict-schema.",
+                found,
+                supported,
+                env!("CARGO_PKG_NAME")
+            ),
             Error::L2RepoReleaseMissingUrl(url) => write!(
                 f,
-                "ERROR: The L2 repo doesn't specify a URL for the requested release {}.",
+                "The L2 repo doesn't specify a URL for the requested release {}.",
                 url
             ),
-            Error::InvalidSection(sec) => write!(f, "ERROR: Invalid section specified {}.", sec),
-            Error::InvalidGroup(grp) => write!(f, "ERROR: Invalid group specified {}.", grp),
+            Error::EmptyRepo(reason) => write!(
+                f,
+                "The repo is empty, {}. The main repo url may be wrong or out of date.",
+                reason
+            ),
+            Error::InvalidSection(sec) => write!(f, "Invalid section specified {}.", sec),
+            Error::InvalidGroup(grp) => write!(f, "Invalid group specified {}.", grp),
             Error::InvalidComponent(cmp) => {
-                write!(f, "ERROR: Invalid component specified {}.", cmp)
+                write!(f, "Invalid component specified {}.", cmp)
             }
-            Error::UnsupportedChecksumType(typ) => write!(
+            Error::HiddenComponent(cmp) => write!(
                 f,
-                "ERROR: Unsupported package checksum type specified {}.",
-                typ
+                "Component {} is hidden in the SDK Manager, pass --include-hidden to select it.",
+                cmp
+            ),
+            Error::UnsupportedChecksumType(typ) => {
+                write!(
+                    f,
+                    "Unsupported package checksum type specified {}. Supported types:",
+                    typ
+                )?;
+                for cktype in crate::checksum::supported_types() {
+                    write!(f, "\t{}", cktype)?;
+                }
+                Ok(())
+            }
+            Error::FileNotExist(p) => write!(f, "The specified file does not exist: {}", p),
+            Error::PackagesFailed {
+                action,
+                succeeded,
+                failed,
+            } => write!(
+                f,
+                "{} failed for {} of {} packages.",
+                action,
+                failed,
+                succeeded + failed
             ),
-            Error::FileNotExist(p) => write!(f, "ERROR: The specified file does not exist: {}", p),
             Error::FileDigestInvalid {
                 file: fil,
                 cktype: ckt,
@@ -175,9 +534,81 @@ impl std::fmt::Display for Error {
                 actual: act,
             } => write!(
                 f,
-                "ERROR: The checksum for {} was invalid {}[{} != {}]",
+                "The checksum for {} was invalid {}[{} != {}]",
                 fil, ckt, act, ex
             ),
+            Error::FileSizeInvalid {
+                file,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "The size of {} was invalid: {} bytes != {} bytes",
+                file, actual, expected
+            ),
+            Error::InsufficientDiskSpace {
+                path,
+                available,
+                required,
+            } => write!(
+                f,
+                "Not enough free space on {}: {} bytes available, {} required",
+                path, available, required
+            ),
+            Error::DiskFull { path } => write!(
+                f,
+                "Ran out of disk space writing {}, free some space on its filesystem and try again",
+                path
+            ),
+            Error::SingleReleaseOnly(option) => {
+                write!(f, "{} can only be used with a single --release", option)
+            }
+            Error::LockfileDrift(drift) => {
+                write!(f, "Repo no longer matches the lockfile: {}", drift)
+            }
+            Error::UnsafeFileName(name) => write!(
+                f,
+                "Refusing the repo supplied name {:?}, it would leave the package directory",
+                name
+            ),
+            Error::StdinConflict(other) => write!(
+                f,
+                "--components-from - and {} can't both read stdin, give one of them a file",
+                other
+            ),
+            Error::NoComponentsListed(source) => write!(f, "No components listed on {}", source),
+            Error::ChecksFailed { passed, failed } => write!(
+                f,
+                "{} of {} checks failed.",
+                failed,
+                passed + failed
+            ),
         }
     }
 }
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ERROR[{}]: ", self.code())?;
+        self.fmt_message(f)
+    }
+}
+
+/// Describe what was being done when a `Result` fails, like
+/// `anyhow::Context`
+pub trait Context<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T>;
+
+    /// Like `context`, but only builds the description on failure
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> Result<T>;
+}
+
+impl<T> Context<T> for Result<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T> {
+        self.map_err(|e| Error::Context(context.into(), Box::new(e)))
+    }
+
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        self.map_err(|e| Error::Context(context().into(), Box::new(e)))
+    }
+}
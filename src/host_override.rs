@@ -0,0 +1,244 @@
+//! `--resolve host:port:addr` support.  reqwest 0.9 has no way to
+//! override name resolution, so connections to overridden hosts are sent
+//! through a small proxy on the loopback interface, which connects to the
+//! given address instead.  HTTPS goes through it as a CONNECT tunnel, so
+//! TLS is still verified against the original host name.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+
+use log::{debug, info, warn};
+
+use crate::error::{Error, Result};
+
+/// A host and port to connect to at a fixed address, like curl's
+/// `--resolve`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostOverride {
+    pub host: String,
+    pub port: u16,
+    pub addr: IpAddr,
+}
+
+impl HostOverride {
+    /// Whether this overrides `host` and `port`
+    pub fn matches(&self, host: &str, port: u16) -> bool {
+        host.eq_ignore_ascii_case(&self.host) && port == self.port
+    }
+
+    fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.addr, self.port)
+    }
+}
+
+impl FromStr for HostOverride {
+    type Err = Error;
+
+    /// Parse `host:port:addr`, where an IPv6 `addr` may be in brackets
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || Error::InvalidResolve(s.to_string());
+        let mut parts = s.splitn(3, ':');
+        let host = parts.next().filter(|h| !h.is_empty()).ok_or_else(invalid)?;
+        let port = parts
+            .next()
+            .and_then(|p| p.parse::<u16>().ok())
+            .ok_or_else(invalid)?;
+        let addr = parts.next().ok_or_else(invalid)?;
+        let addr = addr
+            .strip_prefix('[')
+            .and_then(|a| a.strip_suffix(']'))
+            .unwrap_or(addr)
+            .parse::<IpAddr>()
+            .map_err(|_| invalid())?;
+        Ok(Self {
+            host: host.to_lowercase(),
+            port,
+            addr,
+        })
+    }
+}
+
+impl std::fmt::Display for HostOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.addr {
+            IpAddr::V4(addr) => write!(f, "{}:{}:{}", self.host, self.port, addr),
+            IpAddr::V6(addr) => write!(f, "{}:{}:[{}]", self.host, self.port, addr),
+        }
+    }
+}
+
+/// Start the proxy connecting to `overrides`, returning its url.  It
+/// listens on the loopback address of `local_address`'s family, so clients
+/// bound to that family can reach it.
+pub fn start_proxy(overrides: &[HostOverride], local_address: Option<IpAddr>) -> Result<url::Url> {
+    let loopback = match local_address {
+        Some(IpAddr::V6(_)) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        _ => IpAddr::V4(Ipv4Addr::LOCALHOST),
+    };
+    let listener = TcpListener::bind(SocketAddr::new(loopback, 0))?;
+    let proxy_url = url::Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+    debug!("Proxying --resolve hosts through {}", proxy_url);
+    let overrides = overrides.to_vec();
+    std::thread::Builder::new()
+        .name("resolve-proxy".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("--resolve proxy failed accepting a connection: {}", e);
+                        continue;
+                    }
+                };
+                let overrides = overrides.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_connection(stream, &overrides) {
+                        debug!("--resolve proxy connection ended: {}", e);
+                    }
+                });
+            }
+        })?;
+    Ok(proxy_url)
+}
+
+/// A request for something that isn't overridden, which the client never
+/// sends through the proxy
+fn not_proxied(target: &str) -> Error {
+    Error::from(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{} isn't a --resolve host", target),
+    ))
+}
+
+/// The override for `host` and `port`, connected to
+fn connect(overrides: &[HostOverride], host: &str, port: u16) -> Result<TcpStream> {
+    let host_override = overrides
+        .iter()
+        .find(|o| o.matches(host, port))
+        .ok_or_else(|| not_proxied(&format!("{}:{}", host, port)))?;
+    info!(
+        "Connecting to {}:{} at {} (--resolve)",
+        host, port, host_override.addr
+    );
+    Ok(TcpStream::connect(host_override.socket_addr())?)
+}
+
+/// Read a request's head up to the blank line, or `None` at the end of
+/// the stream
+fn read_head(reader: &mut impl BufRead) -> Result<Option<Vec<String>>> {
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() {
+            return Ok(Some(lines));
+        }
+        lines.push(line);
+    }
+}
+
+fn bad_gateway(client: &mut TcpStream) {
+    let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n");
+}
+
+/// Copy everything the server sends back to the client, on its own thread
+fn relay_responses(server: &TcpStream, client: &TcpStream) -> Result<()> {
+    let mut server = server.try_clone()?;
+    let mut client = client.try_clone()?;
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut server, &mut client);
+        let _ = client.shutdown(std::net::Shutdown::Both);
+    });
+    Ok(())
+}
+
+fn handle_connection(mut client: TcpStream, overrides: &[HostOverride]) -> Result<()> {
+    let mut reader = BufReader::new(client.try_clone()?);
+    let head = match read_head(&mut reader)? {
+        Some(head) => head,
+        None => return Ok(()),
+    };
+    let request_line = head.first().cloned().unwrap_or_default();
+    let mut words = request_line.split_whitespace();
+    let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+
+    // HTTPS: tunnel the bytes, TLS runs end to end through it
+    if method == "CONNECT" {
+        let (host, port) = target.rsplit_once(':').unwrap_or((target, ""));
+        let port = port.parse().unwrap_or(443);
+        let mut server = match connect(overrides, host, port) {
+            Ok(server) => server,
+            Err(e) => {
+                bad_gateway(&mut client);
+                return Err(e);
+            }
+        };
+        client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+        relay_responses(&server, &client)?;
+        // Anything the client sent after the CONNECT is still buffered
+        std::io::copy(&mut reader, &mut server)?;
+        let _ = server.shutdown(std::net::Shutdown::Write);
+        return Ok(());
+    }
+
+    // Plain HTTP: requests come with absolute urls, which are passed on
+    // with just their path.  The client keeps a connection per host, so
+    // every request on it goes to the same server.
+    let url = url::Url::parse(target).map_err(|_| not_proxied(target))?;
+    let host = url.host_str().unwrap_or("").to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let mut server = match connect(overrides, &host, port) {
+        Ok(server) => server,
+        Err(e) => {
+            bad_gateway(&mut client);
+            return Err(e);
+        }
+    };
+    relay_responses(&server, &client)?;
+    let mut head = Some(head);
+    while let Some(lines) = head.take() {
+        forward_request(&lines, &mut reader, &mut server)?;
+        head = read_head(&mut reader)?;
+    }
+    let _ = server.shutdown(std::net::Shutdown::Write);
+    Ok(())
+}
+
+/// Send a request on to the server with an origin-form request line,
+/// followed by its body when it has a length
+fn forward_request(
+    lines: &[String],
+    reader: &mut BufReader<TcpStream>,
+    server: &mut TcpStream,
+) -> Result<()> {
+    let mut words = lines[0].split_whitespace();
+    let method = words.next().unwrap_or("");
+    let target = words.next().unwrap_or("");
+    let version = words.next().unwrap_or("HTTP/1.1");
+    let path = match url::Url::parse(target) {
+        Ok(url) => match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        },
+        Err(_) => target.to_string(),
+    };
+    let mut out = format!("{} {} {}\r\n", method, path, version);
+    let mut body_len = 0;
+    for line in &lines[1..] {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                body_len = value.trim().parse().unwrap_or(0);
+            }
+        }
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    out.push_str("\r\n");
+    server.write_all(out.as_bytes())?;
+    std::io::copy(&mut reader.take(body_len), server)?;
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use crate::error::{Error, Result};
+
+/// Exit status for a run stopped by SIGINT, as shells report it
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+lazy_static! {
+    static ref PARTIAL_FILES: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+/// Marks a partially written file for removal if the run is interrupted,
+/// for as long as it is held
+pub struct PartialFile {
+    path: PathBuf,
+}
+
+impl PartialFile {
+    pub fn track(path: &Path) -> Self {
+        PARTIAL_FILES
+            .lock()
+            .expect("partial files lock poisoned")
+            .insert(path.to_path_buf());
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if let Ok(mut partial_files) = PARTIAL_FILES.lock() {
+            partial_files.remove(&self.path);
+        }
+    }
+}
+
+/// Handle SIGINT by removing any partially written files and exiting.
+///
+/// SIGINT is blocked in the calling thread, and so in every thread it
+/// starts afterwards, then waited for by a dedicated thread where cleaning
+/// up is safe.  Call this before any other threads are started.
+pub fn install_handler() -> Result<()> {
+    // Safety: the signal set is initialized by sigemptyset before use
+    let set = unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        if libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) != 0 {
+            return Err(Error::from(std::io::Error::last_os_error()));
+        }
+        set
+    };
+    std::thread::Builder::new()
+        .name("sigint".to_string())
+        .spawn(move || {
+            let mut signal = 0;
+            // Safety: set is a valid, initialized signal set
+            if unsafe { libc::sigwait(&set, &mut signal) } != 0 {
+                return;
+            }
+            let partial_files = PARTIAL_FILES
+                .lock()
+                .map(|files| files.iter().cloned().collect::<Vec<_>>())
+                .unwrap_or_default();
+            for path in &partial_files {
+                let _ = std::fs::remove_file(path);
+            }
+            eprintln!(
+                "Interrupted, removed {} partial download file(s)",
+                partial_files.len()
+            );
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        })?;
+    Ok(())
+}
@@ -0,0 +1,17 @@
+pub mod actions;
+pub mod cache;
+pub mod caching_client;
+pub mod checksum;
+pub mod error;
+pub mod host_override;
+pub mod interrupt;
+pub mod lockfile;
+pub mod netrc;
+pub mod output;
+mod resolve;
+pub use resolve::{resolve, resolve_persisted, resolve_releases, RESOLVED_REPO_FILE};
+pub mod sdkm;
+pub mod sdkm_config;
+pub mod sdkm_l1;
+pub mod sdkm_l2;
+pub mod sdkm_l3;
@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::checksum;
+use crate::error::{Error, Result};
+use crate::sdkm_l3::{L3ComponentVersion, L3ComponentVersionDownloadFile, L3Repo};
+
+/// The release selection and exact packages of a fetch, to reproduce it
+/// later
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockfile {
+    pub product_category: String,
+    #[serde(rename = "targetOS")]
+    pub target_os: String,
+    pub release: String,
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedPackage {
+    pub component: String,
+    pub version: String,
+    pub file_name: String,
+    pub checksum: String,
+    pub checksum_type: String,
+}
+
+impl Lockfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        debug!("Reading lockfile {}", path.display());
+        serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))
+            .map_err(Error::from)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        debug!("Writing lockfile {}", path.display());
+        serde_json::to_writer_pretty(std::fs::File::create(path)?, self).map_err(Error::from)
+    }
+
+    /// The repo's component version and file for each locked package.  Fails
+    /// on the first package that is gone from the repo or whose checksum
+    /// changed.
+    pub fn resolve<'a>(
+        &self,
+        l3repo: &'a L3Repo,
+    ) -> Result<
+        Vec<(
+            String,
+            &'a L3ComponentVersion,
+            &'a L3ComponentVersionDownloadFile,
+        )>,
+    > {
+        if l3repo.information.release.title != self.release {
+            return Err(Error::LockfileDrift(format!(
+                "locked release {} but the repo is for {}",
+                self.release, l3repo.information.release.title
+            )));
+        }
+        self.packages
+            .iter()
+            .map(|package| {
+                let drift = |reason: &str| {
+                    Error::LockfileDrift(format!(
+                        "{} {} package {} {}",
+                        package.component, package.version, package.file_name, reason
+                    ))
+                };
+                let version = l3repo
+                    .get_component(&package.component)
+                    .and_then(|c| c.versions.iter().find(|v| v.version == package.version))
+                    .ok_or_else(|| drift("is no longer in the repo"))?;
+                let file = version
+                    .download_files
+                    .iter()
+                    .find(|f| f.file_name == package.file_name)
+                    .ok_or_else(|| drift("is no longer in the repo"))?;
+                if file.checksum_type != package.checksum_type
+                    || !checksum::digests_match(&file.checksum, &package.checksum)
+                {
+                    return Err(drift(&format!(
+                        "changed checksum from {}[{}] to {}[{}]",
+                        package.checksum_type, package.checksum, file.checksum_type, file.checksum
+                    )));
+                }
+                Ok((package.component.clone(), version, file))
+            })
+            .collect()
+    }
+}
@@ -1,25 +1,55 @@
 use std::convert::TryFrom;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 use human_panic::setup_panic;
 use log::debug;
+use reqwest::header::{HeaderName, HeaderValue};
 use structopt::StructOpt;
 
-mod error;
-use error::{Error, Result};
-mod cache;
-mod sdkm;
-mod sdkm_config;
-use sdkm_config::SdkmConfig;
-mod sdkm_l1;
-use sdkm_l1::L1Repo;
-mod sdkm_l2;
-use sdkm_l2::L2Repo;
-mod sdkm_l3;
-use sdkm_l3::L3Repo;
-mod actions;
-use actions::{fetch, show, verify, Action};
-mod caching_client;
+use nvsdk_getter::actions::{
+    capabilities, clean, components_json, doctor, explain, export, fetch, list, notes, search,
+    show, verify, Action,
+};
+use nvsdk_getter::cache::{self, ClientOptions};
+use nvsdk_getter::caching_client::{self, CacheKeyHash};
+use nvsdk_getter::error::{Context, Error, Result};
+use nvsdk_getter::host_override::HostOverride;
+use nvsdk_getter::interrupt;
+use nvsdk_getter::lockfile::Lockfile;
+use nvsdk_getter::output::{self, OutputFormat, ProgressMode};
+use nvsdk_getter::sdkm;
+use nvsdk_getter::sdkm_config::SdkmConfig;
+use nvsdk_getter::sdkm_l3::L3Repo;
+use nvsdk_getter::{resolve, resolve_releases, RESOLVED_REPO_FILE};
+
+const CONFIG_ENV_VAR: &str = "NVSDK_CONFIG";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    fn variants() -> Vec<String> {
+        vec!["text".to_string(), "json".to_string()]
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(Error::InvalidLogFormat(s.to_string(), Self::variants())),
+        }
+    }
+}
 
 #[derive(Debug, StructOpt)]
 struct Opt {
@@ -32,14 +62,31 @@ struct Opt {
     verbose: u8,
 
     /// Quiet mode, silence output - this supercedes other
-    /// output control options
+    /// output control options.  The error that stops the tool is
+    /// still reported.
     #[structopt(short, long)]
     quiet: bool,
 
-    /// Path to the sdkm_config.json file from the SDKManager
+    /// When to show progress bars: auto (when stderr is a terminal),
+    /// always (as periodic lines of text without a terminal), or never
+    #[structopt(long, default_value = "auto")]
+    progress: ProgressMode,
+
+    /// Log line format: text, or json for log aggregators
+    #[structopt(long, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Path to the sdkm_config.json file from the SDKManager, or - to
+    /// read it from stdin.  If unset, the NVSDK_CONFIG environment
+    /// variable may hold the config JSON itself.
     #[structopt(short = "c", long, parse(from_os_str))]
     sdkm_config: Option<PathBuf>,
 
+    /// URL of the main (L1) repo index, overriding the one from the
+    /// sdkm config
+    #[structopt(long)]
+    main_repo_url: Option<url::Url>,
+
     /// Product Category, leave unspecified to see a list options
     #[structopt(short, long)]
     product_category: Option<String>,
@@ -48,15 +95,134 @@ struct Opt {
     #[structopt(short, long)]
     target_os: Option<String>,
 
-    /// Product Release, leave unspecified to see a list of options
-    #[structopt(short, long)]
-    release: Option<String>,
+    /// Product Release, leave unspecified to see a list of options.
+    /// Repeat to run the action against each of several releases.
+    #[structopt(short, long, number_of_values = 1)]
+    release: Vec<String>,
 
     /// Cache directory where local copies of packages are kept
     /// Default is <cache_dir>/nvsdk_getter/<Category>/<TargetOS>/<Release>/
+    /// With several releases, each gets a subdirectory of it named after
+    /// the release.
     #[structopt(short = "d", long, parse(from_os_str))]
     cache_dir: Option<PathBuf>,
 
+    /// Output format for show, verify, and --explain: text, json, or yaml
+    #[structopt(long, default_value = "text")]
+    format: OutputFormat,
+
+    /// User agent to send with http requests
+    /// Default is nvsdk_getter/<version>
+    #[structopt(long)]
+    user_agent: Option<String>,
+
+    /// Header to send with every request, as "Name: Value", repeat to
+    /// send several
+    #[structopt(
+        long = "header",
+        number_of_values = 1,
+        parse(try_from_str = cache::parse_header)
+    )]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Idle connections to keep open to each host for reuse
+    #[structopt(long)]
+    max_connections_per_host: Option<usize>,
+
+    /// Redirects to follow for a request before failing it
+    #[structopt(long, default_value = "10")]
+    max_redirects: usize,
+
+    /// Use HTTP/2 without negotiating it first, for servers known to
+    /// support it
+    #[structopt(long)]
+    http2: bool,
+
+    /// Root certificate (PEM or DER) to trust in addition to the system
+    /// ones, repeat to add several
+    #[structopt(long, parse(from_os_str))]
+    ca_cert: Vec<PathBuf>,
+
+    /// PEM client certificate to present to servers
+    #[structopt(long, parse(from_os_str))]
+    client_cert: Option<PathBuf>,
+
+    /// PEM private key for --client-cert
+    /// Default is to read it from the --client-cert file
+    #[structopt(long, parse(from_os_str), requires = "client-cert")]
+    client_key: Option<PathBuf>,
+
+    /// Mirror to download packages from instead of the repo's servers,
+    /// repeat to fail over to further mirrors in order
+    #[structopt(long)]
+    mirror: Vec<url::Url>,
+
+    /// Connect to a host and port at a fixed address instead of resolving
+    /// it, as `<host>:<port>:<address>`, repeat for more hosts
+    #[structopt(long, number_of_values = 1)]
+    resolve: Vec<HostOverride>,
+
+    /// Only connect over IPv4, for networks with broken IPv6 routes
+    #[structopt(long, conflicts_with = "prefer-ipv6")]
+    prefer_ipv4: bool,
+
+    /// Only connect over IPv6
+    #[structopt(long)]
+    prefer_ipv6: bool,
+
+    /// Don't validate server certificates, for development only
+    #[structopt(long)]
+    danger_accept_invalid_certs: bool,
+
+    /// Fail instead of warning when a repo uses a newer schema version
+    /// than this tool supports
+    #[structopt(long)]
+    strict_schema: bool,
+
+    /// Ignore cached validators and always download in full
+    /// Fresh responses are still written to the cache
+    #[structopt(long)]
+    no_cache: bool,
+
+    /// Download the repo JSON files in full, like --no-cache, but keep
+    /// using the cached packages
+    #[structopt(long)]
+    refresh: bool,
+
+    /// Store downloads gzip compressed in the cache.  Fetched packages are
+    /// then copied out decompressed rather than linked.  Entries already
+    /// cached are read either way.
+    #[structopt(long)]
+    compress_cache: bool,
+
+    /// Seconds to reuse the release's component repo resolved by an
+    /// earlier run before walking the repo chain again, 0 to always walk it
+    #[structopt(long, default_value = "3600")]
+    resolved_repo_ttl: u64,
+
+    /// Seconds to use the cached L1/L2/L3 repo JSON files without asking
+    /// the server whether they changed, counted from when they were last
+    /// downloaded or confirmed current.  Default is to always ask.
+    #[structopt(long)]
+    repo_cache_ttl: Option<u64>,
+
+    /// Octal permissions for created cache directories, such as 2775 for
+    /// a cache shared by a group.  Files get the same without execute.
+    /// Default is the umask's.
+    #[structopt(long, parse(try_from_str = cache::parse_mode))]
+    cache_mode: Option<u32>,
+
+    /// Hash naming the http cache entries: fnv, or md5 as older versions
+    /// did.  Entries under their md5 name are moved to the fnv one as
+    /// they're used.
+    #[structopt(long, default_value = "fnv")]
+    cache_key_hash: CacheKeyHash,
+
+    /// Print the effective configuration as JSON and exit without
+    /// contacting any server
+    #[structopt(long)]
+    config_dump: bool,
+
     /// Software section, group, and component actions
     #[structopt(subcommand)]
     action: Action,
@@ -79,81 +245,338 @@ fn get_log_level(opt: &Opt) -> flexi_logger::LevelFilter {
     }
 }
 
-fn main() -> Result<()> {
+fn json_log_format(
+    w: &mut dyn std::io::Write,
+    now: &mut flexi_logger::DeferredNow,
+    record: &log::Record,
+) -> std::result::Result<(), std::io::Error> {
+    let line = serde_json::json!({
+        "timestamp": now.now().to_rfc3339(),
+        "level": record.level().to_string(),
+        "module": record.module_path(),
+        "message": record.args().to_string(),
+    });
+    write!(w, "{}", line)
+}
+
+/// Default is ~/.cache/nvsdk_getter/<Category>/<TargetOS>/<Release>/
+fn get_cache_dir(
+    opt: &Opt,
+    product_category: Option<&str>,
+    target_os: Option<&str>,
+    release: Option<&str>,
+) -> PathBuf {
+    match &opt.cache_dir {
+        Some(dir) if opt.release.len() > 1 => dir.join(release.unwrap_or_default()),
+        Some(dir) => dir.clone(),
+        None => {
+            let dir_str = format!(
+                "{}/{}/{}",
+                product_category.unwrap_or_default(),
+                target_os.unwrap_or_default(),
+                release.unwrap_or_default()
+            );
+            cache::get_cache_dir(Some(Path::new(&dir_str)))
+        }
+    }
+}
+
+/// Everything in effect after defaults, the config, and the command line
+/// are merged
+fn config_dump(
+    opt: &Opt,
+    config: &SdkmConfig,
+    client_options: &ClientOptions,
+    product_category: Option<&str>,
+    target_os: Option<&str>,
+    releases: &[&str],
+) -> Result<()> {
+    let release = releases.first().copied();
+    // The default cache dir is only meaningful once every selection is made
+    let cache_dir = if opt.cache_dir.is_some()
+        || (product_category.is_some() && target_os.is_some() && release.is_some())
+    {
+        Some(get_cache_dir(opt, product_category, target_os, release))
+    } else {
+        None
+    };
+    let dump = serde_json::json!({
+        "sdkmConfig": config,
+        "productCategory": product_category,
+        "targetOS": target_os,
+        "release": release,
+        "releases": releases,
+        "cacheDir": cache_dir,
+        "format": format!("{:?}", opt.format).to_lowercase(),
+        "strictSchema": opt.strict_schema,
+        "resolvedRepoTtlSecs": opt.resolved_repo_ttl,
+        "cacheMode": opt.cache_mode.map(|mode| format!("{:o}", mode)),
+        "cacheKeyHash": format!("{:?}", opt.cache_key_hash).to_lowercase(),
+        "quiet": opt.quiet,
+        "progress": format!("{:?}", opt.progress).to_lowercase(),
+        "client": {
+            "userAgent": client_options.user_agent,
+            // Only the names, values are often credentials
+            "headers": client_options
+                .headers
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            "noCache": client_options.no_cache,
+            "refresh": client_options.refresh,
+            "compressCache": client_options.compress_cache,
+            "maxIdlePerHost": client_options.max_idle_per_host,
+            "http2": client_options.http2,
+            "caCerts": client_options.ca_certs,
+            "clientCert": client_options.client_cert,
+            "clientKey": client_options.client_key,
+            "dangerAcceptInvalidCerts": client_options.danger_accept_invalid_certs,
+            "localAddress": client_options.local_address,
+            "mirrors": client_options.mirrors.iter().map(|m| m.as_str()).collect::<Vec<_>>(),
+            "resolve": client_options.resolve.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+            "maxRedirects": client_options.max_redirects,
+            "metadataTimeoutSecs": client_options.metadata_timeout.map(|t| t.as_secs()),
+            "downloadTimeoutSecs": client_options.download_timeout.map(|t| t.as_secs()),
+            "repoCacheTtlSecs": client_options.repo_cache_ttl.map(|t| t.as_secs()),
+        },
+        "action": format!("{:?}", opt.action),
+    });
+    println!("{}", serde_json::to_string_pretty(&dump)?);
+    Ok(())
+}
+
+fn main() {
     setup_panic!();
     let opt = Opt::from_args();
-    flexi_logger::Logger::with(
+    if let Err(e) = run(opt) {
+        // Printed directly rather than logged so --quiet can't hide why
+        // the tool failed
+        eprintln!("{}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run(mut opt: Opt) -> Result<()> {
+    let logger = flexi_logger::Logger::with(
         flexi_logger::LogSpecification::default(flexi_logger::LevelFilter::Error)
             .module(env!("CARGO_PKG_NAME"), get_log_level(&opt))
+            // The connection pool logs each reuse of a kept-alive
+            // connection at debug, to confirm downloads aren't reconnecting
+            .module("hyper::client::pool", get_log_level(&opt))
             .build(),
-    )
-    .start()
-    .map_err(Error::from)?;
+    );
+    let logger = match opt.log_format {
+        LogFormat::Text => logger,
+        LogFormat::Json => logger.format(json_log_format),
+    };
+    logger.start().map_err(Error::from)?;
     debug!("Parsed args: {:?}", opt);
 
-    let config = opt
-        .sdkm_config
-        .map(|c| SdkmConfig::try_from(c.as_path()))
-        .transpose()?
-        .unwrap_or_else(SdkmConfig::default);
+    // Before the http clients start their threads, so they inherit the
+    // blocked SIGINT
+    interrupt::install_handler()?;
+
+    let mut client_options = ClientOptions::default();
+    if let Some(user_agent) = &opt.user_agent {
+        client_options.user_agent = user_agent.clone();
+    }
+    client_options.headers = opt.headers.clone();
+    client_options.repo_cache_ttl = opt.repo_cache_ttl.map(Duration::from_secs);
+    client_options.no_cache = opt.no_cache;
+    client_options.refresh = opt.refresh;
+    client_options.compress_cache = opt.compress_cache;
+    client_options.max_idle_per_host = opt.max_connections_per_host;
+    client_options.http2 = opt.http2;
+    client_options.ca_certs = opt.ca_cert.clone();
+    client_options.client_cert = opt.client_cert.clone();
+    client_options.client_key = opt.client_key.clone();
+    client_options.danger_accept_invalid_certs = opt.danger_accept_invalid_certs;
+    client_options.mirrors = opt.mirror.clone();
+    client_options.resolve = opt.resolve.clone();
+    client_options.max_redirects = opt.max_redirects;
+    client_options.local_address = if opt.prefer_ipv4 {
+        Some(Ipv4Addr::UNSPECIFIED.into())
+    } else if opt.prefer_ipv6 {
+        Some(Ipv6Addr::UNSPECIFIED.into())
+    } else {
+        None
+    };
+    cache::configure_client(&client_options)?;
+    cache::set_cache_mode(opt.cache_mode);
+    caching_client::set_cache_key_hash(opt.cache_key_hash);
+    sdkm::schema::set_strict(opt.strict_schema);
+    output::set_quiet(opt.quiet);
+    output::set_progress(opt.progress);
+
+    // Read before the config, so - isn't taken by both
+    let config_from_stdin = opt.sdkm_config.as_deref() == Some(Path::new("-"));
+    opt.action
+        .read_components_from(Some("--sdkm-config -").filter(|_| config_from_stdin))
+        .context("while reading --components-from")?;
+
+    let mut config = match &opt.sdkm_config {
+        Some(path) if path.as_path() == Path::new("-") => SdkmConfig::from_reader(std::io::stdin())
+            .context("while reading the SDK Manager config from stdin")?,
+        Some(path) => SdkmConfig::try_from(path.as_path())
+            .with_context(|| format!("while reading the SDK Manager config {}", path.display()))?,
+        None => match std::env::var(CONFIG_ENV_VAR) {
+            Ok(json) => SdkmConfig::try_from(json.as_str()).with_context(|| {
+                format!(
+                    "while reading the SDK Manager config in ${}",
+                    CONFIG_ENV_VAR
+                )
+            })?,
+            Err(_) => SdkmConfig::default(),
+        },
+    };
+    if let Some(main_repo_url) = &opt.main_repo_url {
+        config.main_repo_url = main_repo_url.clone();
+    }
 
     debug!("SDKManager Config: {:?}", config);
 
-    let l1repo = L1Repo::try_from(&config.main_repo_url)?;
-    debug!("L1 Repo: {:?}", l1repo);
+    // An existing lockfile's release selection stands in for a missing one
+    let lockfile = match opt.action.get_lockfile() {
+        Some(path) if path.exists() || opt.action.locked() => Some(
+            Lockfile::load(path)
+                .with_context(|| format!("while reading the lockfile {}", path.display()))?,
+        ),
+        _ => None,
+    };
 
-    let req_product_category = opt
+    // Selections on the command line win over the lockfile's, then the
+    // config's defaults
+    let product_category = opt
         .product_category
-        .ok_or_else(|| Error::MissingProductCategory(l1repo.product_categories()))?;
+        .as_deref()
+        .or(lockfile.as_ref().map(|l| l.product_category.as_str()))
+        .or(config.default_product_category.as_deref());
+    let target_os = opt
+        .target_os
+        .as_deref()
+        .or(lockfile.as_ref().map(|l| l.target_os.as_str()))
+        .or(config.default_target_os.as_deref());
+    let releases: Vec<&str> = if opt.release.is_empty() {
+        lockfile
+            .as_ref()
+            .map(|l| l.release.as_str())
+            .or(config.default_release.as_deref())
+            .into_iter()
+            .collect()
+    } else {
+        opt.release.iter().map(String::as_str).collect()
+    };
 
-    let product_category = l1repo
-        .get_product_category(&req_product_category)
-        .ok_or_else(|| {
-            Error::InvalidProductCategory(req_product_category.clone(), l1repo.product_categories())
-        })?;
-    debug!("Product Category: {:?}", product_category);
+    if opt.config_dump {
+        return config_dump(
+            &opt,
+            &config,
+            &client_options,
+            product_category,
+            target_os,
+            &releases,
+        );
+    }
 
-    let req_target_os = opt
-        .target_os
-        .ok_or_else(|| Error::MissingTargetOS(product_category.product_lines()))?;
-    let product_line = product_category
-        .get_product_line(&req_target_os)
-        .ok_or_else(|| {
-            Error::InvalidTargetOS(req_target_os.clone(), product_category.product_lines())
-        })?;
-    debug!("Target OS: {:?}", product_line);
-
-    let l2_rel_url = l1repo.get_product_url(&req_product_category, &req_target_os)?;
-    debug!("l2_rel_url: {}", l2_rel_url);
-    let l2repo = L2Repo::try_from(&l2_rel_url)?;
-    debug!("L2 Repo: {:?}", l2repo);
-
-    let req_release = opt
-        .release
-        .ok_or_else(|| Error::MissingRelease(l2repo.releases()))?;
-
-    let release = l2repo
-        .get_release(&req_release)
-        .ok_or_else(|| Error::InvalidRelease(req_release.clone(), l2repo.releases()))?;
-    debug!("Release: {:?}", release);
-    let l3_url = l2repo.get_release_url(&req_release)?;
-    debug!("l3_url: {}", l3_url);
-
-    let l3repo = L3Repo::try_from(&l3_url)?;
-    debug!("L3 Repo: {:?}", l3repo);
-
-    // Default is ~/.cache/nvsdk_getter/<Category>/<TargetOS>/<Release>/
-    let cache_dir: PathBuf = opt.cache_dir.unwrap_or_else(|| {
-        let dir_str = format!("{}/{}/{}", req_product_category, req_target_os, req_release);
-        let dir = Path::new(&dir_str);
-        cache::get_cache_dir(Some(&dir))
-    });
-    std::fs::create_dir_all(&cache_dir)?;
-    match &opt.action {
-        Action::Show { .. } => show(&l3repo, &opt.action)?,
-        Action::Fetch { .. } => fetch(&l3repo, &opt.action, &cache_dir)?,
-        Action::Verify { .. } => verify(&l3repo, &opt.action, &cache_dir)?,
+    // Listing the legal selections can't wait for them all to resolve, and
+    // diagnostics and cleanup don't need any
+    match opt.action {
+        Action::List { what } => {
+            return list(&config, what, product_category, target_os, opt.format)
+        }
+        Action::Doctor => return doctor(&config),
+        Action::Capabilities => return capabilities(opt.format),
+        Action::Clean { corrupt } => return clean(corrupt),
+        _ => {}
     }
 
+    // A lockfile pins a single release
+    if releases.len() > 1 && opt.action.get_lockfile().is_some() {
+        return Err(Error::SingleReleaseOnly("--lockfile".to_string()));
+    }
+
+    let l3repos = match (product_category, target_os) {
+        (Some(product_category), Some(target_os)) if !releases.is_empty() => {
+            let max_age = if opt.no_cache || opt.refresh {
+                Duration::from_secs(0)
+            } else {
+                Duration::from_secs(opt.resolved_repo_ttl)
+            };
+            let paths: Vec<PathBuf> = releases
+                .iter()
+                .map(|release| {
+                    get_cache_dir(&opt, Some(product_category), Some(target_os), Some(release))
+                        .join(RESOLVED_REPO_FILE)
+                })
+                .collect();
+            let selections: Vec<(&str, &Path)> = releases
+                .iter()
+                .copied()
+                .zip(paths.iter().map(PathBuf::as_path))
+                .collect();
+            resolve_releases(
+                &config,
+                product_category,
+                target_os,
+                &selections,
+                output::show_progress(),
+                max_age,
+            )?
+        }
+        _ => vec![resolve(
+            &config,
+            product_category,
+            target_os,
+            releases.first().copied(),
+            output::show_progress(),
+        )?],
+    };
+
+    // resolve() has already rejected any missing selections
+    for (release, l3repo) in releases.iter().zip(&l3repos) {
+        let cache_dir = get_cache_dir(&opt, product_category, target_os, Some(release));
+        if releases.len() > 1 {
+            print_release_heading(opt.format, release);
+            run_action(&opt, l3repo, &cache_dir)
+                .with_context(|| format!("while working on release {}", release))?;
+        } else {
+            run_action(&opt, l3repo, &cache_dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Mark where the output for one of several releases starts.  Structured
+/// output gets the heading on stderr so stdout stays parseable.
+fn print_release_heading(format: OutputFormat, release: &str) {
+    match format {
+        OutputFormat::Text => println!("==> Release {} <==", release),
+        _ => eprintln!("==> Release {} <==", release),
+    }
+}
+
+/// Run the selected action against one release's component repo
+fn run_action(opt: &Opt, l3repo: &L3Repo, cache_dir: &Path) -> Result<()> {
+    if opt.action.explain() {
+        return explain(l3repo, &opt.action, opt.format);
+    }
+    if opt.action.components_json() {
+        return components_json(l3repo, &opt.action);
+    }
+    cache::create_cache_dir_all(cache_dir)?;
+    match &opt.action {
+        Action::Show { .. } => show(l3repo, &opt.action, opt.format)?,
+        Action::Fetch { .. } => fetch(l3repo, &opt.action, cache_dir)?,
+        Action::Verify { .. } => verify(l3repo, &opt.action, cache_dir, opt.format)?,
+        Action::Search { query } => search(l3repo, query)?,
+        Action::Notes => notes(l3repo, cache_dir)?,
+        Action::Export { format } => export(l3repo, *format)?,
+        Action::List { .. } | Action::Doctor | Action::Capabilities | Action::Clean { .. } => {
+            unreachable!(
+                "list, doctor, capabilities, and clean are handled before resolving the release"
+            )
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use log::{debug, warn};
+
+const NETRC_ENV_VAR: &str = "NETRC";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub login: String,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Netrc {
+    machines: Vec<(String, Credentials)>,
+    default: Option<Credentials>,
+}
+
+impl Netrc {
+    /// Parse the `machine`, `default`, `login`, and `password` entries of a
+    /// netrc file.  `account` is skipped, as are `macdef` bodies.
+    pub fn parse(contents: &str) -> Self {
+        let mut netrc = Self::default();
+        // The host (None for default) and credentials being built
+        let mut current: Option<(Option<String>, Option<String>, Option<String>)> = None;
+        let mut lines = contents.lines();
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                match token {
+                    "machine" | "default" => {
+                        netrc.push(current.take());
+                        let host = if token == "machine" {
+                            tokens.next().map(str::to_string)
+                        } else {
+                            None
+                        };
+                        current = Some((host, None, None));
+                    }
+                    "login" => {
+                        if let Some(entry) = current.as_mut() {
+                            entry.1 = tokens.next().map(str::to_string);
+                        }
+                    }
+                    "password" => {
+                        if let Some(entry) = current.as_mut() {
+                            entry.2 = tokens.next().map(str::to_string);
+                        }
+                    }
+                    "account" => {
+                        tokens.next();
+                    }
+                    "macdef" => {
+                        // A macro runs until the next blank line
+                        for line in lines.by_ref() {
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        netrc.push(current);
+        netrc
+    }
+
+    fn push(&mut self, entry: Option<(Option<String>, Option<String>, Option<String>)>) {
+        if let Some((host, Some(login), password)) = entry {
+            let credentials = Credentials { login, password };
+            match host {
+                Some(host) => self.machines.push((host, credentials)),
+                None => self.default = Some(credentials),
+            }
+        }
+    }
+
+    /// Credentials for `host`, falling back on the `default` entry
+    pub fn get(&self, host: &str) -> Option<&Credentials> {
+        self.machines
+            .iter()
+            .find(|(machine, _)| machine.eq_ignore_ascii_case(host))
+            .map(|(_, credentials)| credentials)
+            .or(self.default.as_ref())
+    }
+}
+
+/// $NETRC if set, else ~/.netrc
+fn netrc_path() -> Option<PathBuf> {
+    std::env::var_os(NETRC_ENV_VAR)
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".netrc")))
+}
+
+fn load(path: &Path) -> Netrc {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            debug!("Loaded credentials from {}", path.display());
+            Netrc::parse(&contents)
+        }
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Couldn't read {}: {}", path.display(), e);
+            }
+            Netrc::default()
+        }
+    }
+}
+
+lazy_static! {
+    static ref NETRC: Netrc = netrc_path().map(|path| load(&path)).unwrap_or_default();
+}
+
+/// Credentials for `host` from the user's netrc file, if it has any
+pub fn credentials(host: &str) -> Option<&'static Credentials> {
+    NETRC.get(host)
+}
@@ -0,0 +1,192 @@
+use std::io::IsTerminal;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    pub fn variants() -> Vec<String> {
+        vec!["text".to_string(), "json".to_string(), "yaml".to_string()]
+    }
+
+    /// Print a serializable value to stdout in this format.  Text output
+    /// has no generic representation, so callers handle it themselves.
+    pub fn print<T: Serialize>(self, value: &T) -> Result<()> {
+        match self {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            OutputFormat::Yaml => print!("{}", serde_yaml::to_string(value)?),
+            OutputFormat::Text => unreachable!("text output must be handled by the caller"),
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            _ => Err(Error::InvalidFormat(s.to_string(), Self::variants())),
+        }
+    }
+}
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress status output such as progress recaps.  Output the user asked
+/// for, like show listings, is unaffected.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// When to draw progress bars.  --quiet hides them whatever the mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressMode {
+    /// Only when stderr is a terminal
+    Auto,
+    /// Also without a terminal, as a line of text every few seconds
+    Always,
+    Never,
+}
+
+impl ProgressMode {
+    pub fn variants() -> Vec<String> {
+        vec![
+            "auto".to_string(),
+            "always".to_string(),
+            "never".to_string(),
+        ]
+    }
+}
+
+impl FromStr for ProgressMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ProgressMode::Auto),
+            "always" => Ok(ProgressMode::Always),
+            "never" => Ok(ProgressMode::Never),
+            _ => Err(Error::InvalidProgressMode(s.to_string(), Self::variants())),
+        }
+    }
+}
+
+static PROGRESS: AtomicU8 = AtomicU8::new(ProgressMode::Auto as u8);
+
+/// How often progress is written out when there is no terminal to draw on
+const TEXT_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+pub fn set_progress(mode: ProgressMode) {
+    PROGRESS.store(mode as u8, Ordering::Relaxed);
+}
+
+fn progress_mode() -> ProgressMode {
+    match PROGRESS.load(Ordering::Relaxed) {
+        m if m == ProgressMode::Always as u8 => ProgressMode::Always,
+        m if m == ProgressMode::Never as u8 => ProgressMode::Never,
+        _ => ProgressMode::Auto,
+    }
+}
+
+/// Whether progress should be shown at all
+pub fn show_progress() -> bool {
+    !is_quiet() && progress_mode() != ProgressMode::Never
+}
+
+/// A progress bar of `len` bytes, hidden when progress isn't shown
+pub fn progress_bar(len: u64) -> Progress {
+    let terminal = std::io::stderr().is_terminal();
+    if !show_progress() || (!terminal && progress_mode() == ProgressMode::Auto) {
+        return Progress {
+            bar: indicatif::ProgressBar::hidden(),
+            text: None,
+        };
+    }
+    // indicatif only draws on terminals, so without one progress is
+    // written out as text instead
+    let text = if terminal {
+        None
+    } else {
+        Some(Mutex::new(TextProgress {
+            message: String::new(),
+            len,
+            pos: 0,
+            written: Instant::now(),
+        }))
+    };
+    Progress {
+        bar: indicatif::ProgressBar::new(len),
+        text,
+    }
+}
+
+struct TextProgress {
+    message: String,
+    len: u64,
+    pos: u64,
+    written: Instant,
+}
+
+/// A progress bar, or a line of text every few seconds where it can't be
+/// drawn
+pub struct Progress {
+    bar: indicatif::ProgressBar,
+    text: Option<Mutex<TextProgress>>,
+}
+
+impl Progress {
+    pub fn set_style(&self, style: indicatif::ProgressStyle) {
+        self.bar.set_style(style);
+    }
+
+    pub fn set_message(&self, message: &str) {
+        self.bar.set_message(message);
+        if let Some(text) = &self.text {
+            text.lock().expect("progress lock poisoned").message = message.to_string();
+        }
+    }
+
+    pub fn inc(&self, delta: u64) {
+        self.bar.inc(delta);
+        if let Some(text) = &self.text {
+            let mut text = text.lock().expect("progress lock poisoned");
+            text.pos += delta;
+            if text.written.elapsed() >= TEXT_PROGRESS_INTERVAL || text.pos >= text.len {
+                eprintln!(
+                    "{}{} of {} bytes",
+                    if text.message.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{} ", text.message)
+                    },
+                    text.pos,
+                    text.len
+                );
+                text.written = Instant::now();
+            }
+        }
+    }
+
+    pub fn finish_and_clear(&self) {
+        self.bar.finish_and_clear();
+    }
+}
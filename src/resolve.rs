@@ -0,0 +1,341 @@
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{offset, DateTime};
+use indicatif::ProgressBar;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::cache;
+use crate::error::{Context, Error, Result};
+use crate::sdkm::RemoteRepo;
+use crate::sdkm_config::SdkmConfig;
+use crate::sdkm_l1::L1Repo;
+use crate::sdkm_l2::L2Repo;
+use crate::sdkm_l3::L3Repo;
+
+/// Walk the L1 -> L2 -> L3 repo chain starting from the config's main repo
+/// url, returning the component repo for the selected release.  A missing
+/// selection is reported as an error listing the legal values.  With
+/// `progress` a spinner shows which repo is being fetched.
+pub fn resolve(
+    config: &SdkmConfig,
+    product_category: Option<&str>,
+    target_os: Option<&str>,
+    release: Option<&str>,
+    progress: bool,
+) -> Result<L3Repo> {
+    let spinner = spinner(progress);
+    let result = resolve_l2(config, product_category, target_os, &spinner)
+        .and_then(|l2repo| resolve_l3(&l2repo, release, &spinner));
+    spinner.finish_and_clear();
+    result
+}
+
+fn spinner(progress: bool) -> ProgressBar {
+    if progress {
+        let spinner = ProgressBar::new_spinner();
+        spinner.enable_steady_tick(100);
+        spinner
+    } else {
+        ProgressBar::hidden()
+    }
+}
+
+/// The releases (L2) repo for the product category and target OS
+fn resolve_l2(
+    config: &SdkmConfig,
+    product_category: Option<&str>,
+    target_os: Option<&str>,
+    spinner: &ProgressBar,
+) -> Result<L2Repo> {
+    spinner.set_message("Fetching product index...");
+    let l1repo = L1Repo::fetch(config.main_repo_url.as_str())
+        .with_context(|| format!("while loading the main repo {}", config.main_repo_url))?;
+    debug!("L1 Repo: {:?}", l1repo);
+
+    let req_product_category = product_category
+        .ok_or_else(|| Error::MissingProductCategory(l1repo.product_categories()))?;
+
+    let product_category = l1repo
+        .get_product_category(req_product_category)
+        .ok_or_else(|| {
+            Error::InvalidProductCategory(
+                req_product_category.to_string(),
+                l1repo.product_categories(),
+            )
+        })?;
+    debug!("Product Category: {:?}", product_category);
+
+    let req_target_os =
+        target_os.ok_or_else(|| Error::MissingTargetOS(product_category.product_lines()))?;
+    let product_line = product_category
+        .get_product_line(req_target_os)
+        .ok_or_else(|| {
+            Error::InvalidTargetOS(req_target_os.to_string(), product_category.product_lines())
+        })?;
+    debug!("Target OS: {:?}", product_line);
+
+    let l2_rel_url = l1repo
+        .get_product_url(req_product_category, req_target_os)
+        .with_context(|| {
+            format!(
+                "while finding the L2 repo for {} {}",
+                req_product_category, req_target_os
+            )
+        })?;
+    debug!("l2_rel_url: {}", l2_rel_url);
+    spinner.set_message("Fetching releases...");
+    let l2repo = L2Repo::fetch(l2_rel_url.as_str()).with_context(|| {
+        format!(
+            "while loading the L2 repo {} for {} {}",
+            l2_rel_url, req_product_category, req_target_os
+        )
+    })?;
+    debug!("L2 Repo: {:?}", l2repo);
+    Ok(l2repo)
+}
+
+/// The component (L3) repo of `release` from the releases repo
+fn resolve_l3(l2repo: &L2Repo, release: Option<&str>, spinner: &ProgressBar) -> Result<L3Repo> {
+    let req_release = release.ok_or_else(|| Error::MissingRelease(l2repo.releases()))?;
+
+    let release = l2repo
+        .get_release(req_release)
+        .ok_or_else(|| Error::InvalidRelease(req_release.to_string(), l2repo.releases()))?;
+    debug!("Release: {:?}", release);
+    let l3_url = l2repo
+        .get_release_url(req_release)
+        .with_context(|| format!("while finding the L3 repo for release {}", req_release))?;
+    debug!("l3_url: {}", l3_url);
+
+    spinner.set_message("Fetching component repo...");
+    let l3repo = L3Repo::fetch(l3_url.as_str()).with_context(|| {
+        format!(
+            "while loading the L3 repo {} for release {}",
+            l3_url, req_release
+        )
+    })?;
+    debug!("L3 Repo: {:?}", l3repo);
+    Ok(l3repo)
+}
+
+/// File in the per-release cache dir holding the resolved component repo
+pub const RESOLVED_REPO_FILE: &str = "l3repo.json";
+
+/// A resolved component repo saved for later runs, along with the
+/// selections it was resolved for
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResolvedRepo {
+    #[serde(rename = "mainRepoURL")]
+    main_repo_url: String,
+    product_category: String,
+    #[serde(rename = "targetOS")]
+    target_os: String,
+    release: String,
+    source: Option<String>,
+    resolved_at: DateTime<offset::Utc>,
+    repo: L3Repo,
+}
+
+impl ResolvedRepo {
+    fn matches(
+        &self,
+        config: &SdkmConfig,
+        product_category: &str,
+        target_os: &str,
+        release: &str,
+    ) -> bool {
+        self.main_repo_url == config.main_repo_url.as_str()
+            && self.product_category == product_category
+            && self.target_os == target_os
+            && self.release == release
+    }
+}
+
+fn load_resolved(path: &Path) -> Result<ResolvedRepo> {
+    serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))
+        .map_err(Error::from)
+}
+
+fn save_resolved(path: &Path, resolved: &ResolvedRepo) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        cache::create_cache_dir_all(dir)?;
+    }
+    serde_json::to_writer(
+        std::io::BufWriter::new(std::fs::File::create(path)?),
+        resolved,
+    )?;
+    cache::apply_cache_mode(path)
+}
+
+/// Like `resolve`, but reuses the component repo saved at `path` by an
+/// earlier run if it was resolved for the same selections less than
+/// `max_age` ago.  Otherwise the chain is walked and the result saved.
+pub fn resolve_persisted(
+    config: &SdkmConfig,
+    product_category: &str,
+    target_os: &str,
+    release: &str,
+    progress: bool,
+    path: &Path,
+    max_age: Duration,
+) -> Result<L3Repo> {
+    let mut repos = resolve_releases(
+        config,
+        product_category,
+        target_os,
+        &[(release, path)],
+        progress,
+        max_age,
+    )?;
+    Ok(repos.remove(0))
+}
+
+/// Like `resolve_persisted` for several releases of one product category
+/// and target OS, each saved at its own path.  The L1 and L2 repos are
+/// fetched at most once, and only if some release needs resolving again.
+pub fn resolve_releases(
+    config: &SdkmConfig,
+    product_category: &str,
+    target_os: &str,
+    releases: &[(&str, &Path)],
+    progress: bool,
+    max_age: Duration,
+) -> Result<Vec<L3Repo>> {
+    let mut l2repo = None;
+    let spinner = spinner(progress);
+    let result = releases
+        .iter()
+        .map(|(release, path)| {
+            if let Some(repo) =
+                load_fresh(config, product_category, target_os, release, path, max_age)
+            {
+                return Ok(repo);
+            }
+            let l2repo = match &mut l2repo {
+                Some(l2repo) => l2repo,
+                None => l2repo.get_or_insert(resolve_l2(
+                    config,
+                    Some(product_category),
+                    Some(target_os),
+                    &spinner,
+                )?),
+            };
+            let repo = resolve_l3(l2repo, Some(release), &spinner)?;
+            Ok(persist(
+                config,
+                product_category,
+                target_os,
+                release,
+                path,
+                repo,
+            ))
+        })
+        .collect();
+    spinner.finish_and_clear();
+    result
+}
+
+/// The component repo saved at `path`, if it was resolved for the same
+/// selections less than `max_age` ago
+fn load_fresh(
+    config: &SdkmConfig,
+    product_category: &str,
+    target_os: &str,
+    release: &str,
+    path: &Path,
+    max_age: Duration,
+) -> Option<L3Repo> {
+    match load_resolved(path) {
+        Ok(resolved) if resolved.matches(config, product_category, target_os, release) => {
+            // A save time in the future has no age, so it's never fresh
+            let age = offset::Utc::now()
+                .signed_duration_since(resolved.resolved_at)
+                .to_std()
+                .ok();
+            let age = match age {
+                Some(age) => age,
+                None => {
+                    debug!(
+                        "Component repo in {:?} was saved in the future",
+                        path.to_str()
+                    );
+                    return None;
+                }
+            };
+            if age < max_age {
+                let mut repo = resolved.repo;
+                repo.source = resolved.source.and_then(|s| url::Url::parse(&s).ok());
+                // The password isn't saved, it comes back from the cached
+                // L3 json rather than downloads going without it
+                if !repo.information.target_access_info.user.is_empty() {
+                    match repo.source.as_ref().and_then(cached_password) {
+                        Some(password) => repo.information.target_access_info.password = password,
+                        None => {
+                            debug!(
+                                "No target password for the saved component repo, resolving again"
+                            );
+                            return None;
+                        }
+                    }
+                }
+                info!(
+                    "Using component repo for {} resolved {}s ago",
+                    release,
+                    age.as_secs()
+                );
+                return Some(repo);
+            }
+            debug!(
+                "Saved component repo is {}s old, resolving again",
+                age.as_secs()
+            );
+        }
+        Ok(_) => debug!("Saved component repo is for other selections, resolving again"),
+        Err(e) => debug!("No saved component repo at {:?}: {}", path.to_str(), e),
+    }
+    None
+}
+
+/// The target access password from the http cache's copy of the L3 json
+/// at `source`, as the saved repo leaves it out
+fn cached_password(source: &url::Url) -> Option<String> {
+    let cached: serde_json::Value = match cache::cached_copy_json(source.as_str()) {
+        Ok(cached) => cached,
+        Err(e) => {
+            debug!("No cached copy of {}: {}", source, e);
+            return None;
+        }
+    };
+    cached
+        .pointer("/information/targetAccessInfo/password")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Save a freshly resolved component repo at `path` for later runs
+fn persist(
+    config: &SdkmConfig,
+    product_category: &str,
+    target_os: &str,
+    release: &str,
+    path: &Path,
+    repo: L3Repo,
+) -> L3Repo {
+    let resolved = ResolvedRepo {
+        main_repo_url: config.main_repo_url.to_string(),
+        product_category: product_category.to_string(),
+        target_os: target_os.to_string(),
+        release: release.to_string(),
+        source: repo.source.as_ref().map(|s| s.to_string()),
+        resolved_at: offset::Utc::now(),
+        repo,
+    };
+    // Failing to save only costs the next run a re-resolve
+    if let Err(e) = save_resolved(path, &resolved) {
+        warn!("Failed saving component repo to {:?}: {}", path.to_str(), e);
+    }
+    resolved.repo
+}
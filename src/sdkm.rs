@@ -1,6 +1,4 @@
 pub mod url {
-    use std::error::Error;
-
     pub(crate) fn serialize<S>(url: &url::Url, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
@@ -12,6 +10,156 @@ pub mod url {
         D: serde::Deserializer<'de>,
     {
         let url_string: String = serde::Deserialize::deserialize(deserializer)?;
-        url::Url::parse(&url_string).map_err(|e| serde::de::Error::custom(e.description()))
+        url::Url::parse(&url_string).map_err(|e| serde::de::Error::custom(e.to_string()))
+    }
+}
+
+/// Checks that repo files use a schema version this tool understands
+pub mod schema {
+    use std::io::Read;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use log::warn;
+
+    use crate::error::{Error, Result};
+
+    /// Newest L1 repo `information.version` known to parse
+    pub const SUPPORTED_L1_VERSION: &str = "1.0";
+    /// Newest L3 repo `information.schemaVersion` known to parse
+    pub const SUPPORTED_L3_SCHEMA_VERSION: &str = "1.0";
+
+    static STRICT: AtomicBool = AtomicBool::new(false);
+
+    /// Make a newer than supported schema version an error instead of a
+    /// warning
+    pub fn set_strict(strict: bool) {
+        STRICT.store(strict, Ordering::Relaxed);
+    }
+
+    fn version_key(version: &str) -> Vec<u64> {
+        let mut key: Vec<u64> = version
+            .split('.')
+            .map(|part| part.trim().parse().unwrap_or(0))
+            .collect();
+        // "1" and "1.0" are the same version
+        while key.last() == Some(&0) {
+            key.pop();
+        }
+        key
+    }
+
+    /// Compare the version at `pointer` in a raw repo document against the
+    /// supported one
+    pub(crate) fn check(repo: &serde_json::Value, pointer: &str, supported: &str) -> Result<()> {
+        match repo.pointer(pointer).and_then(|v| v.as_str()) {
+            Some(found) => check_version(found, supported),
+            None => {
+                warn!("Repo file has no schema version at {}", pointer);
+                Ok(())
+            }
+        }
+    }
+
+    /// Load a repo file with `load`, then check the version `version`
+    /// reads from it.  If the file doesn't parse, it is read again from
+    /// `reread` to look for a schema version change, which is reported
+    /// ahead of the parse error it causes.
+    pub(crate) fn parse<T, L, F, G, S>(
+        load: L,
+        pointer: &str,
+        supported: &str,
+        version: F,
+        reread: G,
+    ) -> Result<T>
+    where
+        L: FnOnce() -> Result<T>,
+        F: FnOnce(&T) -> &str,
+        G: FnOnce() -> Result<S>,
+        S: Read,
+    {
+        match load() {
+            Ok(repo) => {
+                check_version(version(&repo), supported)?;
+                Ok(repo)
+            }
+            Err(Error::JsonError(parse_error)) => {
+                if let Ok(raw) = reread().and_then(|reader| {
+                    serde_json::from_reader::<_, serde_json::Value>(std::io::BufReader::new(reader))
+                        .map_err(Error::from)
+                }) {
+                    check(&raw, pointer, supported)?;
+                }
+                Err(Error::from(parse_error))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn check_version(found: &str, supported: &str) -> Result<()> {
+        if version_key(found) <= version_key(supported) {
+            return Ok(());
+        }
+        if STRICT.load(Ordering::Relaxed) {
+            return Err(Error::UnsupportedSchemaVersion(
+                found.to_string(),
+                supported.to_string(),
+            ));
+        }
+        warn!(
+            "Repo schema version {} is newer than the supported {}, consider updating {}",
+            found,
+            supported,
+            env!("CARGO_PKG_NAME")
+        );
+        Ok(())
+    }
+}
+
+/// A level of the repo chain, fetched through the metadata cache
+pub trait RemoteRepo: serde::de::DeserializeOwned {
+    /// Pointer to the schema version in the repo file and the newest
+    /// supported version, for levels whose schema version is checked
+    const SCHEMA: Option<(&'static str, &'static str)> = None;
+
+    /// The schema version the repo was parsed with, for levels with a
+    /// `SCHEMA`
+    fn schema_version(&self) -> &str {
+        ""
+    }
+
+    /// Record the url the repo was fetched from, which relative urls in it
+    /// are resolved against
+    fn set_source(&mut self, url: ::url::Url);
+
+    /// What's missing if the repo lists nothing to choose from, such as
+    /// "no releases"
+    fn empty_reason(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Fetch the repo at `url_str` and parse it straight from the cached
+    /// file
+    fn fetch(url_str: &str) -> crate::error::Result<Self> {
+        let url = ::url::Url::parse(url_str)?;
+        let mut repo: Self = match Self::SCHEMA {
+            Some((pointer, supported)) => schema::parse(
+                || crate::cache::cached_get_json(url_str),
+                pointer,
+                supported,
+                |repo: &Self| repo.schema_version(),
+                || crate::cache::cached_get_reader(url_str),
+            )?,
+            None => crate::cache::cached_get_json(url_str)?,
+        };
+        // An empty repo usually means a wrong or stale url rather than
+        // anything the user selected
+        if let Some(reason) = repo.empty_reason() {
+            return Err(crate::error::Error::EmptyRepo(format!(
+                "{} found at {}",
+                reason, url
+            )));
+        }
+        repo.set_source(url);
+        Ok(repo)
     }
 }
@@ -3,7 +3,7 @@ use std::convert::TryFrom;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
-use crate::error::Error;
+use crate::error::{Error, Result};
 use crate::sdkm;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -16,16 +16,37 @@ pub struct SdkmConfig {
     pub pid_server: String,
     #[serde(rename = "DevZoneServer")]
     pub dev_zone_server: String,
+    /// Used when --product-category isn't given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_product_category: Option<String>,
+    /// Used when --target-os isn't given
+    #[serde(rename = "defaultTargetOS")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_target_os: Option<String>,
+    /// Used when --release isn't given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_release: Option<String>,
+}
+
+impl SdkmConfig {
+    pub fn from_reader<R: std::io::Read>(reader: R) -> Result<Self> {
+        serde_json::from_reader(std::io::BufReader::new(reader)).map_err(Error::from)
+    }
 }
 
 impl TryFrom<&std::path::Path> for SdkmConfig {
     type Error = Error;
 
     fn try_from(path: &std::path::Path) -> std::result::Result<Self, Self::Error> {
-        serde_json::from_reader(std::io::BufReader::new(
-            std::fs::File::open(path).map_err(Self::Error::from)?,
-        ))
-        .map_err(Self::Error::from)
+        Self::from_reader(std::fs::File::open(path).map_err(Self::Error::from)?)
+    }
+}
+
+impl TryFrom<&str> for SdkmConfig {
+    type Error = Error;
+
+    fn try_from(json: &str) -> std::result::Result<Self, Self::Error> {
+        serde_json::from_str(json).map_err(Self::Error::from)
     }
 }
 
@@ -40,6 +61,9 @@ impl std::default::Default for SdkmConfig {
             main_repo_url: MAIN_REPO_URL.clone(),
             pid_server: "P".to_string(),
             dev_zone_server: "P".to_string(),
+            default_product_category: None,
+            default_target_os: None,
+            default_release: None,
         }
     }
 }
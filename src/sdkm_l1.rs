@@ -1,12 +1,7 @@
-// Needed to bring in Read trait
-use std::io::Read;
-
-use std::convert::TryFrom;
-
 use serde::{Deserialize, Serialize};
 
-use crate::cache;
 use crate::error::{Error, Result};
+use crate::sdkm::{self, RemoteRepo};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -17,26 +12,24 @@ pub struct L1Repo {
     pub product_categories: Vec<L1ProductCategory>,
 }
 
-impl TryFrom<&str> for L1Repo {
-    type Error = Error;
-
-    fn try_from(url_str: &str) -> std::result::Result<Self, Self::Error> {
-        let mut url_data = String::new();
-        let mut in_file = cache::cached_get_reader(url_str)?;
-        in_file
-            .read_to_string(&mut url_data)
-            .map_err(Self::Error::from)?;
-        let mut tmp: Self = serde_json::from_str(&url_data).map_err(Self::Error::from)?;
-        tmp.source = Some(url::Url::parse(url_str).map_err(Self::Error::from)?);
-        Ok(tmp)
+impl RemoteRepo for L1Repo {
+    const SCHEMA: Option<(&'static str, &'static str)> =
+        Some(("/information/version", sdkm::schema::SUPPORTED_L1_VERSION));
+
+    fn schema_version(&self) -> &str {
+        self.information.version.as_str()
     }
-}
 
-impl TryFrom<&url::Url> for L1Repo {
-    type Error = Error;
+    fn set_source(&mut self, url: url::Url) {
+        self.source = Some(url);
+    }
 
-    fn try_from(url: &url::Url) -> std::result::Result<Self, Self::Error> {
-        Self::try_from(url.as_str())
+    fn empty_reason(&self) -> Option<&'static str> {
+        if self.product_categories.is_empty() {
+            Some("no product categories")
+        } else {
+            None
+        }
     }
 }
 
@@ -59,7 +52,7 @@ impl L1Repo {
             Error::InvalidProductCategory(product_category.to_owned(), self.product_categories())
         })?;
         product_category.get_product_line_url(
-            &self
+            self
                 .source
                 .as_ref()
                 .expect("L1 Repo is missing source field."),
@@ -1,11 +1,7 @@
-// Needed to bring in Read trait
-use std::convert::TryFrom;
-use std::io::Read;
-
 use serde::{Deserialize, Serialize};
 
-use crate::cache;
 use crate::error::{Error, Result};
+use crate::sdkm::RemoteRepo;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -16,26 +12,17 @@ pub struct L2Repo {
     pub releases: Vec<L2Release>,
 }
 
-impl TryFrom<&str> for L2Repo {
-    type Error = Error;
-
-    fn try_from(url_str: &str) -> std::result::Result<Self, Self::Error> {
-        let mut url_data = String::new();
-        let mut in_file = cache::cached_get_reader(url_str)?;
-        in_file
-            .read_to_string(&mut url_data)
-            .map_err(Self::Error::from)?;
-        let mut tmp: Self = serde_json::from_str(&url_data).map_err(Self::Error::from)?;
-        tmp.source = Some(url::Url::parse(url_str).map_err(Self::Error::from)?);
-        Ok(tmp)
+impl RemoteRepo for L2Repo {
+    fn set_source(&mut self, url: url::Url) {
+        self.source = Some(url);
     }
-}
 
-impl TryFrom<&url::Url> for L2Repo {
-    type Error = Error;
-
-    fn try_from(url: &url::Url) -> std::result::Result<Self, Self::Error> {
-        Self::try_from(url.as_str())
+    fn empty_reason(&self) -> Option<&'static str> {
+        if self.releases.is_empty() {
+            Some("no releases")
+        } else {
+            None
+        }
     }
 }
 
@@ -104,13 +91,20 @@ pub struct L2Release {
 }
 
 impl L2Release {
+    /// Url of the release's component (L3) repo.  An absolute
+    /// `comp_repo_url` is used as is, a relative one is joined to `base`.
     pub fn get_url(&self, base: &url::Url) -> Result<url::Url> {
-        base.join(
-            self.comp_repo_url
-                .as_ref()
-                .ok_or_else(|| Error::L2RepoReleaseMissingUrl(self.title.clone()))?,
-        )
-        .map_err(Error::from)
+        let comp_repo_url = self
+            .comp_repo_url
+            .as_ref()
+            .ok_or_else(|| Error::L2RepoReleaseMissingUrl(self.title.clone()))?;
+        match url::Url::parse(comp_repo_url) {
+            Ok(url) => Ok(url),
+            Err(url::ParseError::RelativeUrlWithoutBase) => {
+                base.join(comp_repo_url).map_err(Error::from)
+            }
+            Err(e) => Err(Error::from(e)),
+        }
     }
 }
 
@@ -1,16 +1,12 @@
-// Needed to bring in Read trait
-use std::io::Read;
-
 use std::collections::HashMap;
-use std::collections::HashSet;
-use std::convert::TryFrom;
+use std::collections::{BTreeSet, HashSet};
 
 use log::warn;
 use serde::{Deserialize, Serialize};
 
-use crate::cache;
 use crate::error::Error;
-use crate::sdkm;
+use crate::netrc::Credentials;
+use crate::sdkm::{self, RemoteRepo};
 use crate::sdkm_l2;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -26,26 +22,26 @@ pub struct L3Repo {
     pub components: HashMap<String, L3Component>,
 }
 
-impl TryFrom<&str> for L3Repo {
-    type Error = Error;
+impl RemoteRepo for L3Repo {
+    const SCHEMA: Option<(&'static str, &'static str)> = Some((
+        "/information/schemaVersion",
+        sdkm::schema::SUPPORTED_L3_SCHEMA_VERSION,
+    ));
 
-    fn try_from(url_str: &str) -> std::result::Result<Self, Self::Error> {
-        let mut url_data = String::new();
-        let mut in_file = cache::cached_get_reader(url_str)?;
-        in_file
-            .read_to_string(&mut url_data)
-            .map_err(Self::Error::from)?;
-        let mut tmp: Self = serde_json::from_str(&url_data).map_err(Self::Error::from)?;
-        tmp.source = Some(url::Url::parse(url_str).map_err(Self::Error::from)?);
-        Ok(tmp)
+    fn schema_version(&self) -> &str {
+        self.information.schema_version.as_str()
     }
-}
 
-impl TryFrom<&url::Url> for L3Repo {
-    type Error = Error;
+    fn set_source(&mut self, url: url::Url) {
+        self.source = Some(url);
+    }
 
-    fn try_from(url: &url::Url) -> std::result::Result<Self, Self::Error> {
-        Self::try_from(url.as_str())
+    fn empty_reason(&self) -> Option<&'static str> {
+        if self.sections.is_empty() {
+            Some("no sections")
+        } else {
+            None
+        }
     }
 }
 
@@ -54,18 +50,28 @@ impl L3Repo {
         self.sections.iter().map(|p| p.id.clone()).collect()
     }
 
+    /// Sections that the SDK Manager would list
+    pub fn displayed_sections(&self) -> Vec<String> {
+        self.sections
+            .iter()
+            .filter(|p| p.is_displayed())
+            .map(|p| p.id.clone())
+            .collect()
+    }
+
     pub fn get_section(&self, id: &str) -> Option<&L3Section> {
         self.sections.iter().find(|p| p.id == id)
     }
 
-    pub fn get_components_for_section(&self, id: &str) -> HashSet<String> {
-        let mut components: HashSet<String> = HashSet::new();
+    /// Components of a section's groups, sorted by id
+    pub fn get_components_for_section(&self, id: &str) -> BTreeSet<String> {
+        let mut components: BTreeSet<String> = BTreeSet::new();
         if let Some(section) = self.get_section(id) {
             components.extend(
                 section
                     .groups
                     .iter()
-                    .flat_map(|gr_id| self.get_components_for_group(gr_id).into_iter()),
+                    .flat_map(|gr_id| self.get_components_for_group(gr_id, None).into_iter()),
             );
         } else {
             warn!(
@@ -76,25 +82,45 @@ impl L3Repo {
         components
     }
 
+    /// Group ids, sorted
     pub fn groups(&self) -> Vec<String> {
-        self.groups.keys().map(|g| g.to_owned()).collect()
+        let mut ids: Vec<String> = self.groups.keys().map(|g| g.to_owned()).collect();
+        ids.sort();
+        ids
     }
 
     pub fn get_group(&self, name: &str) -> Option<&L3Group> {
-        self.groups.get(&name.to_owned())
+        self.groups.get(name)
     }
 
-    pub fn get_components_for_group(&self, id: &str) -> HashSet<String> {
-        let mut components: HashSet<String> = HashSet::new();
+    /// Components of the requested version of a group, or of its first
+    /// version if none is requested, sorted by id
+    pub fn get_components_for_group(&self, id: &str, version: Option<&str>) -> BTreeSet<String> {
+        let mut components: BTreeSet<String> = BTreeSet::new();
         if let Some(group) = self.get_group(id) {
-            if let Some(version) = group.versions.first() {
-                components.extend(version.components.iter().map(|cmp| cmp.id.clone()));
-            }
-            if group.versions.len() > 1 {
-                warn!(
-                    "Multiple versions of group {} available, selecting the first...",
-                    id
-                );
+            let group_version = match version {
+                Some(ver) => {
+                    let found = group.versions.iter().find(|v| v.version == ver);
+                    if found.is_none() {
+                        warn!(
+                            "Request for components in group {} version {}, but that version doesn't exist!",
+                            id, ver
+                        );
+                    }
+                    found
+                }
+                None => {
+                    if group.versions.len() > 1 {
+                        warn!(
+                            "Multiple versions of group {} available, selecting the first...",
+                            id
+                        );
+                    }
+                    group.versions.first()
+                }
+            };
+            if let Some(group_version) = group_version {
+                components.extend(group_version.components.iter().map(|cmp| cmp.id.clone()));
             }
         } else {
             warn!(
@@ -105,12 +131,88 @@ impl L3Repo {
         components
     }
 
+    /// Component ids, sorted
     pub fn components(&self) -> Vec<String> {
-        self.components.keys().map(|c| c.to_owned()).collect()
+        let mut ids: Vec<String> = self.components.keys().map(|c| c.to_owned()).collect();
+        ids.sort();
+        ids
+    }
+
+    /// Components that the SDK Manager would list, sorted by id
+    pub fn visible_components(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .components
+            .iter()
+            .filter(|(_, c)| c.is_visible)
+            .map(|(id, _)| id.to_owned())
+            .collect();
+        ids.sort();
+        ids
     }
 
     pub fn get_component(&self, id: &str) -> Option<&L3Component> {
-        self.components.get(&id.to_owned())
+        self.components.get(id)
+    }
+
+    /// Total install size in MB of the referenced component versions.  Each
+    /// component is counted once, and a reference to a version the component
+    /// doesn't list falls back to its first version.
+    pub fn install_size_mb<'a, I>(&self, refs: I) -> f64
+    where
+        I: IntoIterator<Item = &'a L3GroupComponentVersion>,
+    {
+        let mut seen: HashSet<&str> = HashSet::new();
+        refs.into_iter()
+            .filter(|r| seen.insert(r.id.as_str()))
+            .filter_map(|r| {
+                let component = self.get_component(&r.id)?;
+                component
+                    .versions
+                    .iter()
+                    .find(|v| v.version == r.version)
+                    .or_else(|| component.versions.first())
+            })
+            .map(|v| f64::from(v.install_size_mb))
+            .sum()
+    }
+
+    /// Total install size in MB of a section, using the first version of
+    /// each of its groups
+    pub fn get_section_install_size_mb(&self, id: &str) -> f64 {
+        let refs = self
+            .get_section(id)
+            .into_iter()
+            .flat_map(|section| section.groups.iter())
+            .filter_map(|gr_id| self.get_group(gr_id))
+            .filter_map(|group| group.versions.first())
+            .flat_map(|version| version.components.iter());
+        self.install_size_mb(refs)
+    }
+
+    /// Absolute url of a download file, resolved against the url the repo
+    /// was loaded from
+    pub fn download_url(&self, file: &L3ComponentVersionDownloadFile) -> Result<url::Url, Error> {
+        self.source
+            .as_ref()
+            .expect("Source not set on l3 repo!")
+            .join(&file.url)
+            .map_err(Error::from)
+    }
+
+    /// Every download file of every component version in the repo, with its
+    /// component id and resolved url, ordered by component id
+    pub fn all_download_files(
+        &self,
+    ) -> Result<Vec<(String, &L3ComponentVersionDownloadFile, url::Url)>, Error> {
+        let mut files = Vec::new();
+        for id in self.components() {
+            for version in &self.components[&id].versions {
+                for file in &version.download_files {
+                    files.push((id.clone(), file, self.download_url(file)?));
+                }
+            }
+        }
+        Ok(files)
     }
 }
 
@@ -124,15 +226,49 @@ pub struct L3Information {
     pub target_access_info: L3TargetAccessInfo,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct L3TargetAccessInfo {
     pub user: String,
+    /// Never written back out, so a persisted repo doesn't leak it.  A
+    /// repo loaded from disk gets it back from the cached L3 json.
+    #[serde(skip_serializing, default)]
     pub password: String,
     pub host: String,
     pub port: String,
 }
 
+impl std::fmt::Debug for L3TargetAccessInfo {
+    // Keep the password out of debug logs
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("L3TargetAccessInfo")
+            .field("user", &self.user)
+            .field("password", &"********")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .finish()
+    }
+}
+
+impl L3TargetAccessInfo {
+    /// Credentials to download `url` with, if it is on the access host (and
+    /// port, when one is given)
+    pub fn credentials_for(&self, url: &url::Url) -> Option<Credentials> {
+        if self.user.is_empty() || url.host_str() != Some(self.host.as_str()) {
+            return None;
+        }
+        if !self.port.is_empty()
+            && url.port_or_known_default().map(|p| p.to_string()) != Some(self.port.clone())
+        {
+            return None;
+        }
+        Some(Credentials {
+            login: self.user.clone(),
+            password: Some(self.password.clone()),
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct L3Section {
@@ -145,7 +281,17 @@ pub struct L3Section {
     pub groups: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl L3Section {
+    pub fn is_displayed(&self) -> bool {
+        self.displayed.unwrap_or(true)
+    }
+
+    pub fn is_selectable(&self) -> bool {
+        self.selectable.unwrap_or(true)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct L3Group {
     pub id: String,
@@ -157,21 +303,21 @@ pub struct L3Group {
     pub versions: Vec<L3GroupVersion>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct L3GroupVersion {
     pub version: String,
     pub components: Vec<L3GroupComponentVersion>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct L3GroupComponentVersion {
     pub id: String,
     pub version: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct L3Component {
     pub id: String,
@@ -185,6 +331,35 @@ pub struct L3Component {
     pub versions: Vec<L3ComponentVersion>,
 }
 
+impl L3Component {
+    /// Whether any version lists `target_hw` among its supported hardware
+    pub fn supports_target_hw(&self, target_hw: &str) -> bool {
+        self.versions
+            .iter()
+            .any(|v| v.supports_target_hw(target_hw))
+    }
+
+    /// The version named `version`, or the first one when none is named.
+    /// A range like ">=5.1,<6" selects the highest version in it, comparing
+    /// versions as semver.
+    pub fn selected_version(&self, version: Option<&str>) -> Option<&L3ComponentVersion> {
+        let version = match version {
+            Some(version) => version,
+            None => return self.versions.first(),
+        };
+        if let Some(exact) = self.versions.iter().find(|v| v.version == version) {
+            return Some(exact);
+        }
+        let range = version_range(version)?;
+        self.versions
+            .iter()
+            .filter_map(|v| parse_semver(&v.version).map(|semver| (semver, v)))
+            .filter(|(semver, _)| range.matches(semver))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, v)| v)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct L3ComponentVersion {
@@ -199,6 +374,99 @@ pub struct L3ComponentVersion {
     pub external_dependencies: serde_json::Value,
 }
 
+impl L3ComponentVersion {
+    /// Whether this version lists `target_hw` among its supported hardware
+    pub fn supports_target_hw(&self, target_hw: &str) -> bool {
+        self.target_ids.iter().any(|id| id == target_hw)
+    }
+
+    /// Whether this version ships download files specific to one of
+    /// several operating systems
+    pub fn has_os_variants(&self) -> bool {
+        self.operating_systems.len() > 1
+            && self
+                .download_files
+                .iter()
+                .any(|f| !f.os_tags(&self.operating_systems).is_empty())
+    }
+
+    /// Download files needed on `os`: the OS independent ones plus those
+    /// tagged for it.  Without an `os`, every file.
+    pub fn download_files_for_os(&self, os: Option<&str>) -> Vec<&L3ComponentVersionDownloadFile> {
+        self.download_files
+            .iter()
+            .filter(|f| {
+                let tags = f.os_tags(&self.operating_systems);
+                tags.is_empty()
+                    || os.is_none_or(|os| {
+                        tags.iter().any(|tag| normalize_os(tag) == normalize_os(os))
+                    })
+            })
+            .collect()
+    }
+
+    /// Ids of the components this version depends on
+    pub fn dependency_ids(&self) -> Vec<String> {
+        let dependencies = match &self.dependencies {
+            serde_json::Value::Array(dependencies) => dependencies.clone(),
+            serde_json::Value::Null => Vec::new(),
+            dependency => vec![dependency.clone()],
+        };
+        let mut ids = Vec::new();
+        for dependency in dependencies {
+            match serde_json::from_value(dependency) {
+                Ok(L3ComponentDependency::Plain(id)) => ids.push(id),
+                Ok(L3ComponentDependency::Map(map)) => ids.extend(map.get("id").cloned()),
+                Ok(L3ComponentDependency::List(maps)) => {
+                    ids.extend(maps.iter().filter_map(|map| map.get("id").cloned()))
+                }
+                Err(e) => warn!(
+                    "Skipping an unrecognized dependency of version {}: {}",
+                    self.version, e
+                ),
+            }
+        }
+        ids
+    }
+}
+
+/// A version range such as ">=5.1,<6".  A plain version isn't one, it only
+/// selects that exact version.
+fn version_range(spec: &str) -> Option<semver::VersionReq> {
+    if !spec.contains(|c| "<>=~^*,".contains(c)) {
+        return None;
+    }
+    semver::VersionReq::parse(spec).ok()
+}
+
+/// A version as semver, with missing minor and patch numbers filled in so
+/// "10.0" is 10.0.0.  None for versions that aren't semver.
+fn parse_semver(version: &str) -> Option<semver::Version> {
+    if let Ok(semver) = semver::Version::parse(version) {
+        return Some(semver);
+    }
+    let parts: Vec<&str> = version.split('.').collect();
+    let numeric = parts
+        .iter()
+        .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+    if parts.len() >= 3 || !numeric {
+        return None;
+    }
+    let mut padded = version.to_string();
+    for _ in parts.len()..3 {
+        padded.push_str(".0");
+    }
+    semver::Version::parse(&padded).ok()
+}
+
+/// "Ubuntu 18.04" -> "ubuntu1804", the way OS names appear in file names
+fn normalize_os(os: &str) -> String {
+    os.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged, rename_all = "camelCase")]
 pub enum L3ComponentDependency {
@@ -216,6 +484,29 @@ pub struct L3ComponentVersionDownloadFile {
     pub checksum: String,
     pub checksum_type: String,
     pub install_parameters: L3ComponentInstallParameters,
+    /// Operating systems the file is for, when it isn't OS independent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operating_systems: Option<Vec<String>>,
+}
+
+impl L3ComponentVersionDownloadFile {
+    /// Operating systems this file is specific to, empty if it is OS
+    /// independent.  Taken from the file's metadata when present, else from
+    /// the `known` OS names that appear in its file name.
+    pub fn os_tags<'a>(&'a self, known: &'a [String]) -> Vec<&'a str> {
+        if let Some(operating_systems) = &self.operating_systems {
+            return operating_systems.iter().map(String::as_str).collect();
+        }
+        let file_name = normalize_os(&self.file_name);
+        known
+            .iter()
+            .filter(|os| {
+                let os = normalize_os(os);
+                !os.is_empty() && file_name.contains(&os)
+            })
+            .map(String::as_str)
+            .collect()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -232,3 +523,39 @@ pub struct L3ComponentAdditionalParameters {
     pub apt_switch: Option<String>,
     pub post_uninstall_commands: Option<Vec<HashMap<String, String>>>,
 }
+
+impl L3ComponentAdditionalParameters {
+    /// Names of the apt packages listed in `packages_info`.  Entries without
+    /// a recognizable name key contribute all of their values.
+    pub fn apt_package_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for info in self.packages_info.iter().flatten() {
+            match info.get("packageName").or_else(|| info.get("name")) {
+                Some(name) => names.push(name.clone()),
+                None => {
+                    let mut values: Vec<String> = info.values().cloned().collect();
+                    values.sort();
+                    names.extend(values);
+                }
+            }
+        }
+        names
+    }
+
+    /// The commands listed in `post_uninstall_commands`.  Entries without
+    /// a `command` key contribute all of their values.
+    pub fn post_uninstall_command_list(&self) -> Vec<String> {
+        let mut commands = Vec::new();
+        for entry in self.post_uninstall_commands.iter().flatten() {
+            match entry.get("command") {
+                Some(command) => commands.push(command.clone()),
+                None => {
+                    let mut values: Vec<String> = entry.values().cloned().collect();
+                    values.sort();
+                    commands.extend(values);
+                }
+            }
+        }
+        commands
+    }
+}
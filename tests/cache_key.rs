@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use nvsdk_getter::caching_client::{self, CacheKeyHash};
+
+const URL: &str =
+    "https://developer.download.nvidia.com/sdkmanager/sdkm-config/main/sdkml1_repo.json";
+
+fn temp_cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("nvsdk_getter-test-{}", std::process::id()))
+        .join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn same_url_maps_to_the_same_path() {
+    let cache_dir = temp_cache_dir("same_url_maps_to_the_same_path");
+    let path = caching_client::url_cache_path(&cache_dir, URL);
+    assert_eq!(path, cache_dir.join("bb92cd8df625b2bf"));
+    assert_eq!(caching_client::url_cache_path(&cache_dir, URL), path);
+    assert_ne!(
+        caching_client::url_cache_path(&cache_dir, &format!("{}?v=2", URL)),
+        path
+    );
+}
+
+#[test]
+fn product_urls_map_to_distinct_paths() {
+    let cache_dir = temp_cache_dir("product_urls_map_to_distinct_paths");
+    let jetson = caching_client::url_cache_path(
+        &cache_dir,
+        "https://developer.download.nvidia.com/sdkmanager/sdkm-config/main/jetson/sdkml2_jetpack_l4t.json",
+    );
+    let drive = caching_client::url_cache_path(
+        &cache_dir,
+        "https://developer.download.nvidia.com/sdkmanager/sdkm-config/main/drive/sdkml2_drive_linux.json",
+    );
+    assert_ne!(jetson, drive);
+}
+
+#[test]
+fn md5_named_entries_are_migrated() {
+    let cache_dir = temp_cache_dir("md5_named_entries_are_migrated");
+    let legacy = cache_dir.join(CacheKeyHash::Md5.key(URL));
+    std::fs::create_dir_all(&legacy).unwrap();
+    std::fs::write(legacy.join("data"), b"cached").unwrap();
+
+    let path = caching_client::url_cache_path(&cache_dir, URL);
+    assert_eq!(path, cache_dir.join(CacheKeyHash::Fnv.key(URL)));
+    assert_eq!(std::fs::read(path.join("data")).unwrap(), b"cached");
+    assert!(!legacy.exists());
+}
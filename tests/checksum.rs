@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+
+use nvsdk_getter::checksum;
+use nvsdk_getter::error::{Error, Result};
+
+fn write_temp(name: &str, contents: &[u8]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nvsdk_getter-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn builtin_md5() {
+    let path = write_temp("md5", b"hello");
+    assert_eq!(
+        checksum::compute_digest(&path, "md5").unwrap(),
+        "5d41402abc4b2a76b9719d911017c592"
+    );
+}
+
+#[test]
+fn builtin_crc32_is_padded_hex() {
+    let path = write_temp("crc32", b"hello");
+    assert_eq!(
+        checksum::compute_digest(&path, "crc32").unwrap(),
+        "3610a686"
+    );
+}
+
+#[test]
+fn unknown_type_is_unsupported() {
+    let path = write_temp("unknown", b"hello");
+    match checksum::compute_digest(&path, "not-a-checksum") {
+        Err(Error::UnsupportedChecksumType(cktype)) => assert_eq!(cktype, "not-a-checksum"),
+        other => panic!("expected UnsupportedChecksumType, got {:?}", other),
+    }
+}
+
+fn byte_count(path: &Path) -> Result<String> {
+    Ok(std::fs::metadata(path)?.len().to_string())
+}
+
+#[test]
+fn registered_verifier_is_used() {
+    checksum::register("test-bytes", byte_count);
+    assert!(checksum::supported_types().contains(&"test-bytes".to_string()));
+    let path = write_temp("registered", b"hello");
+    assert_eq!(checksum::compute_digest(&path, "test-bytes").unwrap(), "5");
+}
+
+#[test]
+fn digest_cache_skips_unchanged_files() {
+    let path = write_temp("digest-cache", b"hello");
+    let dir = path.parent().unwrap();
+    let mut digests = checksum::DigestCache::new(dir);
+    let compute = || checksum::compute_digest(&path, "md5");
+    let digest = digests.digest(&path, "md5", compute).unwrap();
+    digests.save().unwrap();
+
+    let mut digests = checksum::DigestCache::load(dir);
+    let cached = digests
+        .digest(&path, "md5", || panic!("unchanged file was hashed again"))
+        .unwrap();
+    assert_eq!(cached, digest);
+
+    std::fs::write(&path, b"hello, world").unwrap();
+    let changed = digests.digest(&path, "md5", compute).unwrap();
+    assert_ne!(changed, digest);
+}
+
+#[test]
+fn digests_match_ignores_case_and_padding() {
+    let digest = "5d41402abc4b2a76b9719d911017c592";
+    assert!(checksum::digests_match(digest, digest));
+    assert!(checksum::digests_match(
+        digest,
+        "5D41402ABC4B2A76B9719D911017C592"
+    ));
+    assert!(checksum::digests_match(
+        digest,
+        "  5d41402abc4b2a76b9719d911017c592\n"
+    ));
+    assert!(checksum::digests_match(
+        digest,
+        " 5D41402abc4b2a76b9719d911017C592 "
+    ));
+    assert!(!checksum::digests_match(
+        digest,
+        "5d41402abc4b2a76b9719d911017c593"
+    ));
+    assert!(!checksum::digests_match(digest, ""));
+}
@@ -0,0 +1,183 @@
+//! Shared helpers for the integration tests: a minimal HTTP/1.1 server
+//! serving canned bodies, an isolated cache directory, and the fixture
+//! component repo.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+
+use nvsdk_getter::sdkm_l3::L3Repo;
+
+static CACHE_HOME: Once = Once::new();
+
+/// Point the tool's cache at a directory private to this test process
+pub fn isolate_cache() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nvsdk_getter-it-{}", std::process::id()));
+    CACHE_HOME.call_once(|| {
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("XDG_CACHE_HOME", &dir);
+    });
+    dir
+}
+
+/// The fixture component repo as JSON, for adjusting before it's parsed
+pub fn l3repo_json() -> serde_json::Value {
+    serde_json::from_str(include_str!("../fixtures/l3repo.json")).expect("Failed parsing fixture")
+}
+
+/// A component repo parsed from fixture JSON
+pub fn l3repo_from(json: serde_json::Value) -> L3Repo {
+    serde_json::from_value(json).expect("Failed parsing fixture")
+}
+
+/// The fixture component repo as is
+pub fn load_l3repo() -> L3Repo {
+    l3repo_from(l3repo_json())
+}
+
+/// A request the mock server answered: path, request headers with
+/// lowercased names, and response status
+#[derive(Debug, Clone, PartialEq)]
+pub struct Served {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub status: u16,
+}
+
+impl Served {
+    /// Values of the request header `name`, in the order sent
+    pub fn header(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+}
+
+pub struct MockServer {
+    pub base_url: url::Url,
+    served: Arc<Mutex<Vec<Served>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    /// Serve `routes` (path -> body) on a local port.  Every body gets an
+    /// ETag so revalidation can be answered with 304.
+    pub fn start(routes: HashMap<String, Vec<u8>>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url =
+            url::Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let served = Arc::new(Mutex::new(Vec::new()));
+        let routes = Arc::new(routes);
+        let thread_served = served.clone();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let thread_connections = connections.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                thread_connections.fetch_add(1, Ordering::SeqCst);
+                let routes = routes.clone();
+                let served = thread_served.clone();
+                std::thread::spawn(move || handle_connection(stream, &routes, &served));
+            }
+        });
+        Self {
+            base_url,
+            served,
+            connections,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> url::Url {
+        self.base_url.join(path).unwrap()
+    }
+
+    /// Requests answered so far, in order
+    pub fn served(&self) -> Vec<Served> {
+        self.served.lock().unwrap().clone()
+    }
+
+    pub fn served_paths(&self) -> Vec<String> {
+        self.served().into_iter().map(|s| s.path).collect()
+    }
+
+    /// Connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+fn etag(body: &[u8]) -> String {
+    format!("\"{:x}\"", md5::compute(body))
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    routes: &HashMap<String, Vec<u8>>,
+    served: &Mutex<Vec<Served>>,
+) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return;
+        }
+        let path = request_line
+            .split_whitespace()
+            .nth(1)
+            .unwrap_or("/")
+            .to_string();
+        let mut headers = Vec::new();
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).unwrap_or(0) == 0 {
+                return;
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.push((name.to_lowercase(), value.trim().to_string()));
+            }
+        }
+        let if_none_match = headers
+            .iter()
+            .find(|(name, _)| name == "if-none-match")
+            .map(|(_, value)| value.clone());
+
+        let (status, reason, body, tag) = match routes.get(&path) {
+            Some(body) if if_none_match.as_deref() == Some(etag(body).as_str()) => {
+                (304, "Not Modified", Vec::new(), Some(etag(body)))
+            }
+            Some(body) => (200, "OK", body.clone(), Some(etag(body))),
+            None => (404, "Not Found", Vec::new(), None),
+        };
+        served.lock().unwrap().push(Served {
+            path: path.clone(),
+            headers,
+            status,
+        });
+        let mut response = format!("HTTP/1.1 {} {}\r\n", status, reason);
+        if let Some(tag) = tag {
+            response.push_str(&format!("ETag: {}\r\n", tag));
+        }
+        if status != 304 {
+            response.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        response.push_str("\r\n");
+        if writer.write_all(response.as_bytes()).is_err() || writer.write_all(&body).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use nvsdk_getter::sdkm_l2::L2Release;
+
+fn release_with_comp_repo_url(comp_repo_url: &str) -> L2Release {
+    let mut l3repo = common::l3repo_json();
+    let release = l3repo
+        .pointer_mut("/information/release")
+        .expect("Fixture has no release");
+    release["compRepoURL"] = comp_repo_url.into();
+    serde_json::from_value(release.take()).expect("Failed parsing fixture release")
+}
+
+fn l2_base() -> url::Url {
+    url::Url::parse("https://developer.download.nvidia.com/sdkmanager/sdkm-config/JETSON/sdkml2_jetpack_l4t.json").unwrap()
+}
+
+#[test]
+fn absolute_comp_repo_url_is_used_directly() {
+    let release =
+        release_with_comp_repo_url("https://mirror.example.com/jetpack/sdkml3_jetpack_l4t_43.json");
+    assert_eq!(
+        release.get_url(&l2_base()).unwrap().as_str(),
+        "https://mirror.example.com/jetpack/sdkml3_jetpack_l4t_43.json"
+    );
+}
+
+#[test]
+fn relative_comp_repo_url_is_joined_to_base() {
+    let release = release_with_comp_repo_url("./foo/repo.json");
+    assert_eq!(
+        release.get_url(&l2_base()).unwrap().as_str(),
+        "https://developer.download.nvidia.com/sdkmanager/sdkm-config/JETSON/foo/repo.json"
+    );
+}
+
+#[test]
+fn missing_comp_repo_url_is_an_error() {
+    let mut release = release_with_comp_repo_url("");
+    release.comp_repo_url = None;
+    assert!(release.get_url(&l2_base()).is_err());
+}
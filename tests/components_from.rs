@@ -0,0 +1,46 @@
+mod common;
+
+use structopt::StructOpt;
+
+use nvsdk_getter::actions::Action;
+use nvsdk_getter::error::Error;
+
+#[test]
+fn listed_components_join_the_selection() {
+    let dir = common::isolate_cache().join("listed_components_join_the_selection");
+    std::fs::create_dir_all(&dir).unwrap();
+    let list = dir.join("components.txt");
+    std::fs::write(
+        &list,
+        "# CUDA first\nNV_CUDA_TOOLKIT_COMP:10.0\n\n  NV_L4T_DRIVERS_COMP  \n#NV_HOST_TOOLS_COMP\n",
+    )
+    .unwrap();
+
+    let mut action = Action::from_iter(&[
+        "nvsdk_getter",
+        "fetch",
+        "-c",
+        "NV_L4T_FILE_SYSTEM_AND_OS_COMP",
+        "--components-from",
+        list.to_str().unwrap(),
+    ]);
+    action.read_components_from(None).unwrap();
+    assert_eq!(
+        action.get_components(),
+        [
+            "NV_L4T_FILE_SYSTEM_AND_OS_COMP",
+            "NV_CUDA_TOOLKIT_COMP:10.0",
+            "NV_L4T_DRIVERS_COMP",
+        ]
+    );
+}
+
+#[test]
+fn stdin_is_not_read_twice() {
+    let mut action = Action::from_iter(&["nvsdk_getter", "fetch", "--components-from", "-"]);
+    match action.read_components_from(Some("--sdkm-config -")) {
+        Err(Error::StdinConflict(other)) => assert_eq!(other, "--sdkm-config -"),
+        other => panic!("expected StdinConflict, got {:?}", other),
+    }
+    assert!(action.get_components().is_empty());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use common::MockServer;
+use nvsdk_getter::cache::{self, ClientOptions};
+use nvsdk_getter::{caching_client, checksum};
+
+const BODY: &[u8] = b"a package body that gets stored compressed in the cache";
+
+#[test]
+fn compressed_entries_read_back_decompressed() {
+    let cache_home = common::isolate_cache();
+    cache::configure_client(&ClientOptions {
+        compress_cache: true,
+        ..ClientOptions::default()
+    })
+    .unwrap();
+    let mut routes = HashMap::new();
+    routes.insert("/package.deb".to_string(), BODY.to_vec());
+    let server = MockServer::start(routes);
+    let url = server.url("package.deb");
+
+    let cached = cache::cached_get_path(url.as_str(), None).unwrap();
+    assert_ne!(std::fs::read(&cached.path).unwrap(), BODY);
+    assert_eq!(
+        caching_client::data_compression(&cached.path),
+        Some(caching_client::Compression::Gzip)
+    );
+    assert_eq!(
+        caching_client::data_size(&cached.path).unwrap(),
+        BODY.len() as u64
+    );
+    assert_eq!(
+        checksum::compute_digest(&cached.path, "md5").unwrap(),
+        format!("{:x}", md5::compute(BODY))
+    );
+
+    let copy = cache_home.join("package.deb");
+    caching_client::copy_data(&cached.path, &copy).unwrap();
+    assert_eq!(std::fs::read(&copy).unwrap(), BODY);
+
+    let mut read_back = Vec::new();
+    cache::cached_get_reader(url.as_str())
+        .unwrap()
+        .read_to_end(&mut read_back)
+        .unwrap();
+    assert_eq!(read_back, BODY);
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::MockServer;
+use nvsdk_getter::cache;
+
+#[test]
+fn downloads_share_one_connection() {
+    common::isolate_cache();
+    let routes: HashMap<String, Vec<u8>> = (0..5)
+        .map(|i| {
+            (
+                format!("/package-{}.deb", i),
+                format!("package {}", i).into_bytes(),
+            )
+        })
+        .collect();
+    let server = MockServer::start(routes);
+
+    for i in 0..5 {
+        let url = server.url(&format!("package-{}.deb", i));
+        cache::cached_get_path(url.as_str(), None).unwrap();
+    }
+    assert_eq!(server.served().len(), 5);
+    assert_eq!(server.connections(), 1);
+}
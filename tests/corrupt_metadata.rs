@@ -0,0 +1,44 @@
+mod common;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use common::MockServer;
+use nvsdk_getter::{actions, cache, caching_client};
+
+const BODY: &[u8] = b"cached before the metadata schema changed";
+
+#[test]
+fn unreadable_metadata_is_a_miss_and_cleaned() {
+    common::isolate_cache();
+    let mut routes = HashMap::new();
+    routes.insert("/package.deb".to_string(), BODY.to_vec());
+    let server = MockServer::start(routes);
+    let url = server.url("package.deb");
+    let http_cache = cache::get_cache_dir(Some(Path::new("http_cache")));
+    let metadata_path = caching_client::url_metadata_cache_path(&http_cache, url.as_str());
+
+    cache::cached_get_path(url.as_str(), None).unwrap();
+    std::fs::write(&metadata_path, b"{\"source\": 42}").unwrap();
+    assert_eq!(
+        caching_client::corrupt_entries(&http_cache).unwrap(),
+        vec![caching_client::url_cache_path(&http_cache, url.as_str())]
+    );
+
+    // Downloaded in full again rather than failing on the metadata
+    let cached = cache::cached_get_path(url.as_str(), None).unwrap();
+    assert_eq!(std::fs::read(&cached.path).unwrap(), BODY);
+    assert_eq!(
+        server.served().iter().map(|s| s.status).collect::<Vec<_>>(),
+        vec![200, 200]
+    );
+    assert!(caching_client::corrupt_entries(&http_cache)
+        .unwrap()
+        .is_empty());
+
+    std::fs::write(&metadata_path, b"not json").unwrap();
+    actions::clean(false).unwrap();
+    assert!(metadata_path.exists());
+    actions::clean(true).unwrap();
+    assert!(!caching_client::url_cache_path(&http_cache, url.as_str()).exists());
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::MockServer;
+use nvsdk_getter::cache::{self, ClientOptions};
+use nvsdk_getter::error::Error;
+
+#[test]
+fn malformed_headers_are_rejected() {
+    let (name, value) = cache::parse_header("X-Auth-Gateway:  token ").unwrap();
+    assert_eq!(name.as_str(), "x-auth-gateway");
+    assert_eq!(value.to_str().unwrap(), "token");
+    assert!(!format!("{:?}", value).contains("token"));
+    for header in &[
+        "no colon",
+        ": value",
+        "Bad Name: value",
+        "X-Ok: line\nbreak",
+    ] {
+        match cache::parse_header(header) {
+            Err(Error::MalformedHeader(h)) => assert_eq!(h, *header),
+            other => panic!("{:?} gave {:?}", header, other),
+        }
+    }
+}
+
+#[test]
+fn headers_are_sent_with_every_request() {
+    common::isolate_cache();
+    cache::configure_client(&ClientOptions {
+        headers: vec![
+            cache::parse_header("X-Auth-Gateway: token").unwrap(),
+            cache::parse_header("X-Extra: one").unwrap(),
+            cache::parse_header("X-Extra: two").unwrap(),
+            cache::parse_header("User-Agent: gateway-client").unwrap(),
+        ],
+        ..ClientOptions::default()
+    })
+    .unwrap();
+    let mut routes = HashMap::new();
+    routes.insert("/repo.json".to_string(), b"{}".to_vec());
+    routes.insert("/package.deb".to_string(), b"package".to_vec());
+    let server = MockServer::start(routes);
+
+    cache::cached_get_document(server.url("repo.json").as_str()).unwrap();
+    cache::cached_get_path(server.url("package.deb").as_str(), None).unwrap();
+
+    let served = server.served();
+    assert_eq!(served.len(), 2);
+    for request in &served {
+        assert_eq!(request.header("x-auth-gateway"), vec!["token"]);
+        assert_eq!(request.header("x-extra"), vec!["one, two"]);
+        assert_eq!(request.header("user-agent"), vec!["gateway-client"]);
+    }
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use nvsdk_getter::sdkm_l3::L3Repo;
+
+fn load_fixture_with_dependencies(dependencies: serde_json::Value) -> L3Repo {
+    let mut l3repo = common::l3repo_json();
+    l3repo["components"]["NV_CUDA_TOOLKIT_COMP"]["versions"][0]["dependencies"] = dependencies;
+    common::l3repo_from(l3repo)
+}
+
+fn cuda_dependency_ids(l3repo: &L3Repo) -> Vec<String> {
+    l3repo
+        .get_component("NV_CUDA_TOOLKIT_COMP")
+        .unwrap()
+        .versions[0]
+        .dependency_ids()
+}
+
+#[test]
+fn dependency_ids_come_from_every_form() {
+    let l3repo = load_fixture_with_dependencies(serde_json::json!([
+        "NV_L4T_DRIVERS_COMP",
+        { "id": "NV_HOST_TOOLS_COMP" },
+        [{ "id": "NV_L4T_FILE_SYSTEM_AND_OS_COMP" }],
+    ]));
+    assert_eq!(
+        cuda_dependency_ids(&l3repo),
+        vec![
+            "NV_L4T_DRIVERS_COMP",
+            "NV_HOST_TOOLS_COMP",
+            "NV_L4T_FILE_SYSTEM_AND_OS_COMP",
+        ]
+    );
+}
+
+#[test]
+fn empty_or_null_dependencies_have_no_ids() {
+    let l3repo = load_fixture_with_dependencies(serde_json::json!([]));
+    assert!(cuda_dependency_ids(&l3repo).is_empty());
+    let l3repo = load_fixture_with_dependencies(serde_json::Value::Null);
+    assert!(cuda_dependency_ids(&l3repo).is_empty());
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use std::collections::HashMap;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+use common::MockServer;
+use nvsdk_getter::error::Error;
+use nvsdk_getter::{cache, caching_client};
+
+#[test]
+fn running_out_of_space_is_a_disk_full_error() {
+    let full = Path::new("/dev/full");
+    if !full.exists() {
+        return;
+    }
+    common::isolate_cache();
+    let mut routes = HashMap::new();
+    routes.insert("/package.deb".to_string(), vec![0; 64 * 1024]);
+    let server = MockServer::start(routes);
+    let url = server.url("package.deb");
+
+    // Writes to /dev/full fail with ENOSPC, as on a full filesystem
+    let http_cache = cache::get_cache_dir(Some(Path::new("http_cache")));
+    let entry = caching_client::url_cache_path(&http_cache, url.as_str());
+    std::fs::create_dir_all(&entry).unwrap();
+    symlink(full, entry.join("data.partial")).unwrap();
+
+    let err = cache::cached_get_path(url.as_str(), None).err().unwrap();
+    match err.root() {
+        Error::DiskFull { path } => assert!(path.ends_with("data"), "{}", path),
+        other => panic!("expected a disk full error, got {:?}", other),
+    }
+    assert_eq!(err.code(), "E_DISK_FULL");
+    assert_eq!(err.exit_code(), 5);
+    assert!(std::fs::symlink_metadata(entry.join("data.partial")).is_err());
+    assert!(!entry.join("data").exists());
+}
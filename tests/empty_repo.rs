@@ -0,0 +1,71 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::MockServer;
+use nvsdk_getter::error::Error;
+use nvsdk_getter::sdkm_config::SdkmConfig;
+
+/// Serve the fixture L1 and L2 repos with `emptied` (a fixture file name
+/// and the list in it) cleared out
+fn start_server(emptied: (&str, &str)) -> MockServer {
+    let fixtures = vec![
+        (
+            "sdkml1_repo.json",
+            include_str!("fixtures/sdkml1_repo.json"),
+        ),
+        (
+            "jetson/sdkml2_jetpack_l4t.json",
+            include_str!("fixtures/sdkml2_jetpack_l4t.json"),
+        ),
+    ];
+    let mut routes = HashMap::new();
+    for (path, body) in fixtures {
+        let mut repo: serde_json::Value = serde_json::from_str(body).unwrap();
+        if path == emptied.0 {
+            repo[emptied.1] = serde_json::json!([]);
+        }
+        routes.insert(format!("/{}", path), serde_json::to_vec(&repo).unwrap());
+    }
+    MockServer::start(routes)
+}
+
+fn resolve(server: &MockServer) -> Error {
+    let config = SdkmConfig {
+        main_repo_url: server.url("sdkml1_repo.json"),
+        ..SdkmConfig::default()
+    };
+    nvsdk_getter::resolve(&config, Some("Jetson"), Some("Linux"), None, false)
+        .err()
+        .unwrap()
+}
+
+#[test]
+fn empty_repos_point_at_their_url() {
+    common::isolate_cache();
+    let server = start_server(("sdkml1_repo.json", "productCategories"));
+    let err = resolve(&server);
+    match err.root() {
+        Error::EmptyRepo(reason) => assert_eq!(
+            *reason,
+            format!(
+                "no product categories found at {}",
+                server.url("sdkml1_repo.json")
+            )
+        ),
+        other => panic!("expected EmptyRepo, got {:?}", other),
+    }
+    assert_eq!(err.code(), "E_EMPTY_REPO");
+
+    let server = start_server(("jetson/sdkml2_jetpack_l4t.json", "releases"));
+    match resolve(&server).root() {
+        Error::EmptyRepo(reason) => assert_eq!(
+            *reason,
+            format!(
+                "no releases found at {}",
+                server.url("jetson/sdkml2_jetpack_l4t.json")
+            )
+        ),
+        other => panic!("expected EmptyRepo, got {:?}", other),
+    }
+}
@@ -0,0 +1,34 @@
+use nvsdk_getter::error::{Context, Error, Result};
+
+fn failing() -> Result<()> {
+    Err(Error::InvalidComponent("NV_MISSING_COMP".to_string()))
+}
+
+#[test]
+fn context_chain_is_displayed_outermost_first() {
+    let e = failing()
+        .context("while selecting components")
+        .with_context(|| format!("while fetching release {}", "JetPack 4.3"))
+        .unwrap_err();
+    let plain = failing().unwrap_err().to_string();
+    let message = plain.trim_start_matches("ERROR[E_INVALID_COMPONENT]: ");
+    assert_eq!(
+        e.to_string(),
+        format!(
+            "ERROR[E_INVALID_COMPONENT]: while fetching release JetPack 4.3: \
+             while selecting components: {}",
+            message
+        )
+    );
+}
+
+#[test]
+fn context_keeps_the_wrapped_code_and_exit_code() {
+    let e = failing().context("while selecting components").unwrap_err();
+    assert_eq!(e.code(), "E_INVALID_COMPONENT");
+    assert_eq!(e.exit_code(), 2);
+    match e.root() {
+        Error::InvalidComponent(id) => assert_eq!(id, "NV_MISSING_COMP"),
+        other => panic!("expected InvalidComponent, got {:?}", other),
+    }
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use std::collections::HashMap;
+use std::os::unix::fs::{symlink, MetadataExt};
+
+use common::MockServer;
+use nvsdk_getter::cache;
+
+const BODY: &[u8] = b"a package body linked into the output directory";
+
+#[test]
+fn symlinks_become_hard_links_to_the_cached_files() {
+    let cache_home = common::isolate_cache();
+    let mut routes = HashMap::new();
+    routes.insert("/package.deb".to_string(), BODY.to_vec());
+    let server = MockServer::start(routes);
+    let cached = cache::cached_get_path(server.url("package.deb").as_str(), None).unwrap();
+
+    let output_dir = cache_home.join("output");
+    let nested = output_dir.join("component").join("1.0");
+    std::fs::create_dir_all(&nested).unwrap();
+    symlink(&cached.path, output_dir.join("package.deb")).unwrap();
+    symlink(&cached.path, nested.join("package.deb")).unwrap();
+    symlink(cache_home.join("missing"), output_dir.join("dangling.deb")).unwrap();
+
+    assert_eq!(cache::flatten_symlinks(&output_dir).unwrap(), 2);
+    let cached_inode = std::fs::metadata(&cached.path).unwrap().ino();
+    for path in &[output_dir.join("package.deb"), nested.join("package.deb")] {
+        let meta = std::fs::symlink_metadata(path).unwrap();
+        assert!(meta.file_type().is_file());
+        assert_eq!(meta.ino(), cached_inode);
+        assert_eq!(std::fs::read(path).unwrap(), BODY);
+    }
+    assert!(std::fs::symlink_metadata(output_dir.join("dangling.deb"))
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(cache::flatten_symlinks(&output_dir).unwrap(), 0);
+}
@@ -0,0 +1,36 @@
+use nvsdk_getter::netrc::{Credentials, Netrc};
+
+const NETRC: &str = "\
+machine developer.download.nvidia.com
+    login alice
+    password s3cret
+macdef init
+    cd /pub
+    bin
+
+machine mirror.example.com login bob password hunter2 account ops
+default login anonymous password guest
+";
+
+#[test]
+fn matches_machine_case_insensitively() {
+    let netrc = Netrc::parse(NETRC);
+    assert_eq!(
+        netrc.get("Developer.Download.NVIDIA.com"),
+        Some(&Credentials {
+            login: "alice".to_string(),
+            password: Some("s3cret".to_string()),
+        })
+    );
+    assert_eq!(netrc.get("mirror.example.com").unwrap().login, "bob");
+}
+
+#[test]
+fn falls_back_on_default() {
+    let netrc = Netrc::parse(NETRC);
+    assert_eq!(
+        netrc.get("elsewhere.example.com").unwrap().login,
+        "anonymous"
+    );
+    assert_eq!(Netrc::parse("machine a login b").get("c"), None);
+}
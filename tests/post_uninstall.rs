@@ -0,0 +1,39 @@
+mod common;
+
+use nvsdk_getter::sdkm_l3::L3Repo;
+
+fn load_fixture_with_commands(commands: serde_json::Value) -> L3Repo {
+    let mut l3repo = common::l3repo_json();
+    l3repo["components"]["NV_CUDA_TOOLKIT_COMP"]["versions"][0]["downloadFiles"][0]
+        ["installParameters"]["additionalParameters"]["postUninstallCommands"] = commands;
+    common::l3repo_from(l3repo)
+}
+
+fn cuda_commands(l3repo: &L3Repo) -> Vec<String> {
+    l3repo
+        .get_component("NV_CUDA_TOOLKIT_COMP")
+        .unwrap()
+        .versions[0]
+        .download_files[0]
+        .install_parameters
+        .additional_parameters
+        .post_uninstall_command_list()
+}
+
+#[test]
+fn post_uninstall_commands_are_listed_in_order() {
+    let l3repo = load_fixture_with_commands(serde_json::json!([
+        { "command": "apt-key del 7fa2af80" },
+        { "cmd": "rm -rf /var/cuda-repo-10-0-local" },
+    ]));
+    assert_eq!(
+        cuda_commands(&l3repo),
+        vec!["apt-key del 7fa2af80", "rm -rf /var/cuda-repo-10-0-local"]
+    );
+}
+
+#[test]
+fn missing_post_uninstall_commands_list_nothing() {
+    let l3repo = load_fixture_with_commands(serde_json::Value::Null);
+    assert!(cuda_commands(&l3repo).is_empty());
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use common::MockServer;
+use nvsdk_getter::cache::{self, ClientOptions};
+use nvsdk_getter::caching_client;
+
+#[test]
+fn repo_json_within_the_ttl_is_used_without_revalidating() {
+    common::isolate_cache();
+    let mut routes = HashMap::new();
+    routes.insert("/repo.json".to_string(), b"{\"fresh\": true}".to_vec());
+    let server = MockServer::start(routes);
+    let url = server.url("repo.json");
+    let get = || cache::cached_get_json::<serde_json::Value>(url.as_str()).unwrap();
+    let ttl_options = ClientOptions {
+        repo_cache_ttl: Some(Duration::from_secs(3600)),
+        ..ClientOptions::default()
+    };
+
+    cache::configure_client(&ttl_options).unwrap();
+    assert_eq!(get()["fresh"], true);
+    assert_eq!(get()["fresh"], true);
+    assert_eq!(server.served().len(), 1);
+
+    // Without a ttl, and with --refresh, the server is asked again
+    cache::configure_client(&ClientOptions::default()).unwrap();
+    get();
+    cache::configure_client(&ClientOptions {
+        refresh: true,
+        ..ttl_options.clone()
+    })
+    .unwrap();
+    get();
+    let statuses: Vec<u16> = server.served().iter().map(|s| s.status).collect();
+    assert_eq!(statuses, vec![200, 304, 200]);
+
+    // Once the ttl has passed the entry is revalidated, which starts it
+    // over
+    let metadata_path = caching_client::url_metadata_cache_path(
+        &cache::get_cache_dir(Some(Path::new("http_cache"))),
+        url.as_str(),
+    );
+    let mut metadata: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&metadata_path).unwrap()).unwrap();
+    metadata["timestamp"] = "2000-01-01T00:00:00Z".into();
+    std::fs::write(&metadata_path, serde_json::to_vec(&metadata).unwrap()).unwrap();
+    cache::configure_client(&ttl_options).unwrap();
+    get();
+    get();
+    let statuses: Vec<u16> = server.served().iter().map(|s| s.status).collect();
+    assert_eq!(statuses, vec![200, 304, 200, 304]);
+}
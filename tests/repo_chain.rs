@@ -0,0 +1,648 @@
+mod common;
+
+use std::collections::HashMap;
+
+use structopt::StructOpt;
+
+use common::{MockServer, Served};
+use nvsdk_getter::actions::{components_json, explain, fetch, notes, verify, Action};
+use nvsdk_getter::lockfile::Lockfile;
+use nvsdk_getter::output::OutputFormat;
+use nvsdk_getter::sdkm_config::SdkmConfig;
+
+const CUDA_DEB: &str = "cuda-repo-l4t-10-0-local-10.0.326_1.0-1_arm64.deb";
+const CUDA_DEB_BODY: &[u8] = b"not really a debian package";
+const RELEASE_NOTES: &str = "jetpack_43_release_notes.html";
+const RELEASE_NOTES_BODY: &[u8] = b"<html>JetPack 4.3</html>";
+
+/// Serve the fixture repo chain, with the CUDA package's checksum and size
+/// matching the body the server hands out
+fn start_server() -> MockServer {
+    start_server_with(|_| {})
+}
+
+/// Like `start_server`, with the L3 json adjusted by `edit` first
+fn start_server_with(edit: impl FnOnce(&mut serde_json::Value)) -> MockServer {
+    let mut l3repo = common::l3repo_json();
+    edit(&mut l3repo);
+    let cuda_file = l3repo
+        .pointer_mut("/components/NV_CUDA_TOOLKIT_COMP/versions/0/downloadFiles/0")
+        .unwrap();
+    cuda_file["checksum"] = format!("{:x}", md5::compute(CUDA_DEB_BODY)).into();
+    cuda_file["size"] = CUDA_DEB_BODY.len().into();
+
+    let mut routes = HashMap::new();
+    routes.insert(
+        "/sdkml1_repo.json".to_string(),
+        include_bytes!("fixtures/sdkml1_repo.json").to_vec(),
+    );
+    routes.insert(
+        "/jetson/sdkml2_jetpack_l4t.json".to_string(),
+        include_bytes!("fixtures/sdkml2_jetpack_l4t.json").to_vec(),
+    );
+    routes.insert(
+        "/jetson/sdkml3_jetpack_l4t_43.json".to_string(),
+        serde_json::to_vec(&l3repo).unwrap(),
+    );
+    routes.insert(format!("/jetson/{}", CUDA_DEB), CUDA_DEB_BODY.to_vec());
+    routes.insert(
+        format!("/jetson/notes/{}", RELEASE_NOTES),
+        RELEASE_NOTES_BODY.to_vec(),
+    );
+    MockServer::start(routes)
+}
+
+fn config(server: &MockServer) -> SdkmConfig {
+    SdkmConfig {
+        main_repo_url: server.url("sdkml1_repo.json"),
+        ..SdkmConfig::default()
+    }
+}
+
+fn resolve(server: &MockServer) -> nvsdk_getter::sdkm_l3::L3Repo {
+    nvsdk_getter::resolve(
+        &config(server),
+        Some("Jetson"),
+        Some("Linux"),
+        Some("JetPack 4.3"),
+        false,
+    )
+    .unwrap()
+}
+
+#[test]
+fn resolve_walks_the_repo_chain() {
+    common::isolate_cache();
+    let server = start_server();
+    let l3repo = resolve(&server);
+    assert_eq!(l3repo.information.release.title, "JetPack 4.3");
+    assert_eq!(
+        server.served_paths(),
+        vec![
+            "/sdkml1_repo.json",
+            "/jetson/sdkml2_jetpack_l4t.json",
+            "/jetson/sdkml3_jetpack_l4t_43.json",
+        ]
+    );
+}
+
+#[test]
+fn missing_release_lists_the_legal_values() {
+    common::isolate_cache();
+    let server = start_server();
+    match nvsdk_getter::resolve(&config(&server), Some("Jetson"), Some("Linux"), None, false) {
+        Err(nvsdk_getter::error::Error::MissingRelease(releases)) => {
+            assert_eq!(releases, vec!["JetPack 4.3"])
+        }
+        other => panic!("expected MissingRelease, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn fetch_downloads_once_then_revalidates() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let l3repo = resolve(&server);
+    let out_dir = cache_home.join("fetch_downloads_once_then_revalidates");
+    let action = Action::from_iter(&["nvsdk_getter", "fetch", "-c", "NV_CUDA_TOOLKIT_COMP:10.0"]);
+
+    fetch(&l3repo, &action, &out_dir).unwrap();
+    assert_eq!(
+        std::fs::read(out_dir.join(CUDA_DEB)).unwrap(),
+        CUDA_DEB_BODY
+    );
+    fetch(&l3repo, &action, &out_dir).unwrap();
+
+    let package_requests: Vec<Served> = server
+        .served()
+        .into_iter()
+        .filter(|s| s.path.ends_with(CUDA_DEB))
+        .collect();
+    assert_eq!(
+        package_requests
+            .iter()
+            .map(|s| s.status)
+            .collect::<Vec<_>>(),
+        vec![200, 304]
+    );
+    assert_eq!(
+        std::fs::read(out_dir.join(CUDA_DEB)).unwrap(),
+        CUDA_DEB_BODY
+    );
+
+    let action = Action::from_iter(&["nvsdk_getter", "verify", "-c", "NV_CUDA_TOOLKIT_COMP:10.0"]);
+    verify(&l3repo, &action, &out_dir, OutputFormat::Text).unwrap();
+}
+
+#[test]
+fn persisted_repo_is_reused_within_its_ttl() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let path = cache_home
+        .join("persisted_repo_is_reused_within_its_ttl")
+        .join(nvsdk_getter::RESOLVED_REPO_FILE);
+    let resolve_persisted = |max_age| {
+        nvsdk_getter::resolve_persisted(
+            &config(&server),
+            "Jetson",
+            "Linux",
+            "JetPack 4.3",
+            false,
+            &path,
+            std::time::Duration::from_secs(max_age),
+        )
+        .unwrap()
+    };
+
+    resolve_persisted(3600);
+    assert_eq!(server.served().len(), 3);
+    let l3repo = resolve_persisted(3600);
+    assert_eq!(server.served().len(), 3);
+    assert_eq!(l3repo.information.release.title, "JetPack 4.3");
+    assert!(l3repo.source.is_some());
+
+    // The target's password stays out of the saved repo
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let access_info = &saved["repo"]["information"]["targetAccessInfo"];
+    assert_eq!(access_info["user"], "nvidia");
+    assert!(access_info.get("password").is_none());
+
+    // A save time in the future doesn't count as fresh
+    let mut future = saved.clone();
+    future["resolvedAt"] = (chrono::Utc::now() + chrono::Duration::days(1))
+        .to_rfc3339()
+        .into();
+    std::fs::write(&path, future.to_string()).unwrap();
+    resolve_persisted(3600);
+    assert_eq!(server.served().len(), 6);
+
+    // Expired, so the chain is walked again
+    resolve_persisted(0);
+    assert_eq!(server.served().len(), 9);
+}
+
+#[test]
+fn persisted_repo_still_authenticates_to_the_target_access_host() {
+    let cache_home = common::isolate_cache();
+    // The mock server stands in for the target access host, on any port
+    let server = start_server_with(|l3repo| {
+        l3repo["information"]["targetAccessInfo"]["host"] = "127.0.0.1".into();
+        l3repo["information"]["targetAccessInfo"]["port"] = "".into();
+    });
+    let dir = cache_home.join("persisted_repo_still_authenticates_to_the_target_access_host");
+    let path = dir.join(nvsdk_getter::RESOLVED_REPO_FILE);
+    let resolve_persisted = || {
+        nvsdk_getter::resolve_persisted(
+            &config(&server),
+            "Jetson",
+            "Linux",
+            "JetPack 4.3",
+            false,
+            &path,
+            std::time::Duration::from_secs(3600),
+        )
+        .unwrap()
+    };
+    let action = Action::from_iter(&["nvsdk_getter", "fetch", "-c", "NV_CUDA_TOOLKIT_COMP:10.0"]);
+    let package_auth = || {
+        let served = server.served();
+        let last = served
+            .iter()
+            .rev()
+            .find(|s| s.path.ends_with(CUDA_DEB))
+            .unwrap();
+        last.header("authorization")
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+    };
+    // Basic auth for the fixture's nvidia:nvidia
+    let expected = vec!["Basic bnZpZGlhOm52aWRpYQ==".to_string()];
+
+    fetch(&resolve_persisted(), &action, &dir.join("first")).unwrap();
+    assert_eq!(package_auth(), expected);
+
+    // Loaded from disk, without asking for the repo chain again
+    let served = server.served().len();
+    let l3repo = resolve_persisted();
+    assert_eq!(server.served().len(), served);
+    fetch(&l3repo, &action, &dir.join("second")).unwrap();
+    assert_eq!(package_auth(), expected);
+}
+
+#[test]
+fn several_releases_share_the_l1_and_l2_repos() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let dir = cache_home.join("several_releases_share_the_l1_and_l2_repos");
+    let (first, second) = (dir.join("first.json"), dir.join("second.json"));
+    let l3repos = nvsdk_getter::resolve_releases(
+        &config(&server),
+        "Jetson",
+        "Linux",
+        &[("JetPack 4.3", &first), ("JetPack 4.3", &second)],
+        false,
+        std::time::Duration::from_secs(3600),
+    )
+    .unwrap();
+
+    assert_eq!(l3repos.len(), 2);
+    assert_eq!(
+        server.served_paths(),
+        vec![
+            "/sdkml1_repo.json",
+            "/jetson/sdkml2_jetpack_l4t.json",
+            "/jetson/sdkml3_jetpack_l4t_43.json",
+            "/jetson/sdkml3_jetpack_l4t_43.json",
+        ]
+    );
+    assert!(first.exists() && second.exists());
+}
+
+#[test]
+fn fetch_stops_before_going_below_min_free_space() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let l3repo = resolve(&server);
+    let out_dir = cache_home.join("fetch_stops_before_going_below_min_free_space");
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "fetch",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+        "--min-free-space",
+        "1000000000000",
+    ]);
+
+    match fetch(&l3repo, &action, &out_dir) {
+        Err(nvsdk_getter::error::Error::InsufficientDiskSpace { .. }) => {}
+        other => panic!("expected InsufficientDiskSpace, got {:?}", other),
+    }
+    assert!(!server.served_paths().iter().any(|p| p.ends_with(CUDA_DEB)));
+}
+
+#[test]
+fn min_free_space_counts_only_packages_still_to_download() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let l3repo = resolve(&server);
+    let out_dir = cache_home.join("min_free_space_counts_only_packages_still_to_download");
+    let fetch_args = |extra: &[&'static str]| {
+        let mut args = vec!["nvsdk_getter", "fetch", "-c", "NV_CUDA_TOOLKIT_COMP:10.0"];
+        args.extend_from_slice(extra);
+        Action::from_iter(&args)
+    };
+
+    fetch(&l3repo, &fetch_args(&[]), &out_dir).unwrap();
+    // Already cached, so linking it needs no room
+    fetch(
+        &l3repo,
+        &fetch_args(&["--min-free-space", "1000000000000"]),
+        &out_dir,
+    )
+    .unwrap();
+}
+
+#[test]
+fn locked_fetch_reports_drifted_packages() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let l3repo = resolve(&server);
+    let out_dir = cache_home.join("locked_fetch_reports_drifted_packages");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let lockfile = out_dir.join("nvsdk.lock");
+    let lockfile_arg = lockfile.to_str().unwrap();
+
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "fetch",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+        "--lockfile",
+        lockfile_arg,
+    ]);
+    fetch(&l3repo, &action, &out_dir).unwrap();
+    let mut locked = Lockfile::load(&lockfile).unwrap();
+    assert_eq!(locked.release, "JetPack 4.3");
+    assert_eq!(locked.packages.len(), 1);
+    assert_eq!(locked.packages[0].file_name, CUDA_DEB);
+
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "fetch",
+        "--lockfile",
+        lockfile_arg,
+        "--locked",
+    ]);
+    fetch(&l3repo, &action, &out_dir).unwrap();
+
+    locked.packages[0].checksum = "0".repeat(32);
+    locked.save(&lockfile).unwrap();
+    match fetch(&l3repo, &action, &out_dir) {
+        Err(nvsdk_getter::error::Error::LockfileDrift(drift)) => {
+            assert!(drift.contains(CUDA_DEB), "{}", drift)
+        }
+        other => panic!("expected LockfileDrift, got {:?}", other),
+    }
+}
+
+#[test]
+fn verify_checks_only_the_selected_version() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let mut l3repo = resolve(&server);
+    let out_dir = cache_home.join("verify_checks_only_the_selected_version");
+    let versions = &mut l3repo
+        .components
+        .get_mut("NV_CUDA_TOOLKIT_COMP")
+        .unwrap()
+        .versions;
+    let mut newer = versions[0].clone();
+    newer.version = "10.2".to_string();
+    newer.download_files[0].file_name = "cuda-repo-l4t-10-2-local_arm64.deb".to_string();
+    versions.push(newer);
+
+    let action = Action::from_iter(&["nvsdk_getter", "fetch", "-c", "NV_CUDA_TOOLKIT_COMP"]);
+    fetch(&l3repo, &action, &out_dir).unwrap();
+    assert!(out_dir.join(CUDA_DEB).exists());
+    assert!(!out_dir.join("cuda-repo-l4t-10-2-local_arm64.deb").exists());
+
+    let action = Action::from_iter(&["nvsdk_getter", "verify", "-c", "NV_CUDA_TOOLKIT_COMP"]);
+    verify(&l3repo, &action, &out_dir, OutputFormat::Text).unwrap();
+
+    let action = Action::from_iter(&["nvsdk_getter", "verify", "-c", "NV_CUDA_TOOLKIT_COMP:10.2"]);
+    match verify(&l3repo, &action, &out_dir, OutputFormat::Text) {
+        Err(nvsdk_getter::error::Error::FileNotExist(file)) => {
+            assert!(
+                file.ends_with("cuda-repo-l4t-10-2-local_arm64.deb"),
+                "{}",
+                file
+            )
+        }
+        other => panic!("expected FileNotExist, got {:?}", other),
+    }
+}
+
+#[test]
+fn release_notes_are_downloaded_only_when_offered() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let mut l3repo = resolve(&server);
+    let out_dir = cache_home.join("release_notes_are_downloaded_only_when_offered");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let release_notes = &mut l3repo.information.release.release_notes;
+    release_notes.release_notes_url = format!("notes/{}", RELEASE_NOTES);
+
+    notes(&l3repo, &out_dir).unwrap();
+    assert!(!out_dir.join(RELEASE_NOTES).exists());
+
+    l3repo
+        .information
+        .release
+        .release_notes
+        .release_notes_download = true;
+    notes(&l3repo, &out_dir).unwrap();
+    assert_eq!(
+        std::fs::read(out_dir.join(RELEASE_NOTES)).unwrap(),
+        RELEASE_NOTES_BODY
+    );
+}
+
+#[test]
+fn verify_accepts_uppercase_and_padded_checksums() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let mut l3repo = resolve(&server);
+    let out_dir = cache_home.join("verify_accepts_uppercase_and_padded_checksums");
+    let action = Action::from_iter(&["nvsdk_getter", "fetch", "-c", "NV_CUDA_TOOLKIT_COMP:10.0"]);
+    fetch(&l3repo, &action, &out_dir).unwrap();
+
+    let checksum = l3repo.components["NV_CUDA_TOOLKIT_COMP"].versions[0].download_files[0]
+        .checksum
+        .clone();
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "verify",
+        "--force",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+    ]);
+    for formatted in &[
+        checksum.to_uppercase(),
+        format!("  {} ", checksum),
+        format!("\t{}\n", checksum.to_uppercase()),
+    ] {
+        l3repo
+            .components
+            .get_mut("NV_CUDA_TOOLKIT_COMP")
+            .unwrap()
+            .versions[0]
+            .download_files[0]
+            .checksum = formatted.clone();
+        verify(&l3repo, &action, &out_dir, OutputFormat::Text).unwrap();
+    }
+}
+
+#[test]
+fn verify_skips_unsupported_checksum_types_unless_strict() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let mut l3repo = resolve(&server);
+    let out_dir = cache_home.join("verify_skips_unsupported_checksum_types_unless_strict");
+    let action = Action::from_iter(&["nvsdk_getter", "fetch", "-c", "NV_CUDA_TOOLKIT_COMP:10.0"]);
+    fetch(&l3repo, &action, &out_dir).unwrap();
+
+    l3repo
+        .components
+        .get_mut("NV_CUDA_TOOLKIT_COMP")
+        .unwrap()
+        .versions[0]
+        .download_files[0]
+        .checksum_type = "sha3-512".to_string();
+    let action = Action::from_iter(&["nvsdk_getter", "verify", "-c", "NV_CUDA_TOOLKIT_COMP:10.0"]);
+    verify(&l3repo, &action, &out_dir, OutputFormat::Text).unwrap();
+
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "verify",
+        "--strict",
+        "--keep-going",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+    ]);
+    match verify(&l3repo, &action, &out_dir, OutputFormat::Text) {
+        Err(nvsdk_getter::error::Error::UnsupportedChecksumType(cktype)) => {
+            assert_eq!(cktype, "sha3-512")
+        }
+        other => panic!("expected UnsupportedChecksumType, got {:?}", other),
+    }
+}
+
+#[test]
+fn verify_size_only_checks_sizes_without_hashing() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let mut l3repo = resolve(&server);
+    let out_dir = cache_home.join("verify_size_only_checks_sizes_without_hashing");
+    let action = Action::from_iter(&["nvsdk_getter", "fetch", "-c", "NV_CUDA_TOOLKIT_COMP:10.0"]);
+    fetch(&l3repo, &action, &out_dir).unwrap();
+
+    let size_only = Action::from_iter(&[
+        "nvsdk_getter",
+        "verify",
+        "--size-only",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+    ]);
+    let cuda_file = &mut l3repo
+        .components
+        .get_mut("NV_CUDA_TOOLKIT_COMP")
+        .unwrap()
+        .versions[0]
+        .download_files[0];
+    cuda_file.checksum = "0".repeat(32);
+    verify(&l3repo, &size_only, &out_dir, OutputFormat::Text).unwrap();
+
+    l3repo
+        .components
+        .get_mut("NV_CUDA_TOOLKIT_COMP")
+        .unwrap()
+        .versions[0]
+        .download_files[0]
+        .size += 1;
+    match verify(&l3repo, &size_only, &out_dir, OutputFormat::Text) {
+        Err(nvsdk_getter::error::Error::FileSizeInvalid {
+            expected, actual, ..
+        }) => {
+            assert_eq!(actual, CUDA_DEB_BODY.len() as u64);
+            assert_eq!(expected, actual + 1);
+        }
+        other => panic!("expected FileSizeInvalid, got {:?}", other),
+    }
+
+    std::fs::remove_file(out_dir.join(CUDA_DEB)).unwrap();
+    match verify(&l3repo, &size_only, &out_dir, OutputFormat::Text) {
+        Err(nvsdk_getter::error::Error::FileNotExist(_)) => {}
+        other => panic!("expected FileNotExist, got {:?}", other),
+    }
+}
+
+#[test]
+fn fetch_replaces_a_dangling_symlink() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let l3repo = resolve(&server);
+    let out_dir = cache_home.join("fetch_replaces_a_dangling_symlink");
+    let package = out_dir.join(CUDA_DEB);
+    let gone = out_dir.join("gone.deb");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    std::os::unix::fs::symlink(&gone, &package).unwrap();
+
+    let action = Action::from_iter(&["nvsdk_getter", "fetch", "-c", "NV_CUDA_TOOLKIT_COMP:10.0"]);
+    fetch(&l3repo, &action, &out_dir).unwrap();
+    assert_ne!(std::fs::read_link(&package).unwrap(), gone);
+    assert_eq!(std::fs::read(&package).unwrap(), CUDA_DEB_BODY);
+
+    // A copy mustn't be written through the link to wherever it points
+    std::fs::remove_file(&package).unwrap();
+    std::os::unix::fs::symlink(&gone, &package).unwrap();
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "fetch",
+        "--copy",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+    ]);
+    fetch(&l3repo, &action, &out_dir).unwrap();
+    assert!(std::fs::symlink_metadata(&package)
+        .unwrap()
+        .file_type()
+        .is_file());
+    assert_eq!(std::fs::read(&package).unwrap(), CUDA_DEB_BODY);
+    assert!(!gone.exists());
+}
+
+#[test]
+fn explain_downloads_nothing() {
+    common::isolate_cache();
+    let server = start_server();
+    let l3repo = resolve(&server);
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "fetch",
+        "--explain",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+    ]);
+    explain(&l3repo, &action, OutputFormat::Json).unwrap();
+    assert!(!server
+        .served_paths()
+        .iter()
+        .any(|path| path.ends_with(CUDA_DEB)));
+}
+
+#[test]
+fn components_json_downloads_nothing() {
+    common::isolate_cache();
+    let server = start_server();
+    let l3repo = resolve(&server);
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "fetch",
+        "--components-json",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+    ]);
+    components_json(&l3repo, &action).unwrap();
+    assert!(!server
+        .served_paths()
+        .iter()
+        .any(|path| path.ends_with(CUDA_DEB)));
+}
+
+#[test]
+fn skip_if_unchanged_fetches_only_a_changed_build() {
+    let cache_home = common::isolate_cache();
+    let server = start_server();
+    let mut l3repo = resolve(&server);
+    let out_dir = cache_home.join("skip_if_unchanged_fetches_only_a_changed_build");
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "fetch",
+        "--skip-if-unchanged",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+    ]);
+    let package_requests = || {
+        server
+            .served_paths()
+            .iter()
+            .filter(|path| path.ends_with(CUDA_DEB))
+            .count()
+    };
+
+    fetch(&l3repo, &action, &out_dir).unwrap();
+    assert_eq!(package_requests(), 1);
+    fetch(&l3repo, &action, &out_dir).unwrap();
+    assert_eq!(package_requests(), 1);
+
+    l3repo.information.release.release_build = "next".to_string();
+    fetch(&l3repo, &action, &out_dir).unwrap();
+    assert_eq!(package_requests(), 2);
+
+    // Same build, but packages placed differently
+    let hierarchical = Action::from_iter(&[
+        "nvsdk_getter",
+        "fetch",
+        "--skip-if-unchanged",
+        "--layout",
+        "hierarchical",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP:10.0",
+    ]);
+    fetch(&l3repo, &hierarchical, &out_dir).unwrap();
+    assert_eq!(package_requests(), 3);
+    fetch(&l3repo, &hierarchical, &out_dir).unwrap();
+    assert_eq!(package_requests(), 3);
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::MockServer;
+use nvsdk_getter::cache::{self, ClientOptions};
+use nvsdk_getter::error::Error;
+use nvsdk_getter::host_override::HostOverride;
+
+#[test]
+fn malformed_overrides_are_rejected() {
+    let host_override: HostOverride = "Developer.Download.Example:443:[::1]".parse().unwrap();
+    assert_eq!(host_override.host, "developer.download.example");
+    assert_eq!(host_override.port, 443);
+    assert!(host_override.matches("developer.download.example", 443));
+    assert!(!host_override.matches("developer.download.example", 80));
+    assert_eq!(
+        host_override.to_string(),
+        "developer.download.example:443:[::1]"
+    );
+    for value in &[
+        "no-port",
+        "host:443",
+        ":443:127.0.0.1",
+        "host:https:127.0.0.1",
+        "host:443:not-an-address",
+    ] {
+        match value.parse::<HostOverride>() {
+            Err(Error::InvalidResolve(v)) => assert_eq!(v, *value),
+            other => panic!("{:?} gave {:?}", value, other),
+        }
+    }
+}
+
+#[test]
+fn overridden_hosts_are_fetched_from_the_given_address() {
+    common::isolate_cache();
+    let mut routes = HashMap::new();
+    routes.insert("/repo.json".to_string(), b"{}".to_vec());
+    routes.insert("/package.deb".to_string(), b"package".to_vec());
+    let server = MockServer::start(routes);
+    let port = server.url("").port().unwrap();
+    cache::configure_client(&ClientOptions {
+        resolve: vec![format!("repo.invalid:{}:127.0.0.1", port).parse().unwrap()],
+        ..ClientOptions::default()
+    })
+    .unwrap();
+
+    let base = format!("http://repo.invalid:{}", port);
+    cache::cached_get_document(&format!("{}/repo.json", base)).unwrap();
+    let cached = cache::cached_get_path(&format!("{}/package.deb", base), None).unwrap();
+
+    assert_eq!(std::fs::read(&cached.path).unwrap(), b"package");
+    let served = server.served();
+    assert_eq!(served.len(), 2);
+    for request in &served {
+        assert_eq!(
+            request.header("host"),
+            vec![format!("repo.invalid:{}", port)]
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+
+use nvsdk_getter::actions::{safe_path_component, Layout};
+use nvsdk_getter::error::Error;
+
+#[test]
+fn traversal_attempts_are_refused() {
+    for name in &[
+        "",
+        ".",
+        "..",
+        "../../etc/passwd",
+        "/etc/passwd",
+        "sub/package.deb",
+        "..\\..\\package.deb",
+        "%2e%2e",
+        "%2E%2E%2Fetc%2Fpasswd",
+        "package.deb%00.txt",
+    ] {
+        match safe_path_component(name) {
+            Err(Error::UnsafeFileName(refused)) => assert_eq!(refused, *name),
+            other => panic!("expected UnsafeFileName for {:?}, got {:?}", name, other),
+        }
+    }
+}
+
+#[test]
+fn plain_names_are_kept() {
+    for name in &[
+        "cuda-repo-l4t-10-0-local-10.0.326_1.0-1_arm64.deb",
+        "..hidden",
+        "package..deb",
+        "100%25.tar.gz",
+    ] {
+        assert_eq!(safe_path_component(name).unwrap(), *name);
+    }
+}
+
+#[test]
+fn hierarchical_layout_checks_every_component() {
+    let dir = Path::new("/cache");
+    assert_eq!(
+        Layout::Hierarchical
+            .package_path(dir, "NV_CUDA_TOOLKIT_COMP", "10.0", "cuda.deb")
+            .unwrap(),
+        dir.join("NV_CUDA_TOOLKIT_COMP")
+            .join("10.0")
+            .join("cuda.deb")
+    );
+    assert!(Layout::Hierarchical
+        .package_path(dir, "..", "10.0", "cuda.deb")
+        .is_err());
+    assert!(Layout::Hierarchical
+        .package_path(dir, "NV_CUDA_TOOLKIT_COMP", "../..", "cuda.deb")
+        .is_err());
+    assert!(Layout::Flat
+        .package_path(dir, "NV_CUDA_TOOLKIT_COMP", "10.0", "../cuda.deb")
+        .is_err());
+}
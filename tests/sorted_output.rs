@@ -0,0 +1,36 @@
+mod common;
+
+use common::load_l3repo;
+
+#[test]
+fn component_and_group_ids_are_sorted() {
+    let l3repo = load_l3repo();
+    assert_eq!(
+        l3repo.components(),
+        vec![
+            "NV_CUDA_TOOLKIT_COMP",
+            "NV_HOST_TOOLS_COMP",
+            "NV_L4T_DRIVERS_COMP",
+            "NV_L4T_FILE_SYSTEM_AND_OS_COMP",
+        ]
+    );
+    assert_eq!(
+        l3repo.visible_components(),
+        vec![
+            "NV_CUDA_TOOLKIT_COMP",
+            "NV_L4T_DRIVERS_COMP",
+            "NV_L4T_FILE_SYSTEM_AND_OS_COMP",
+        ]
+    );
+    assert_eq!(l3repo.groups(), vec!["CUDA", "DEV_TOOLS", "L4T"]);
+}
+
+#[test]
+fn ordering_is_stable_across_loads() {
+    let first = load_l3repo();
+    for _ in 0..10 {
+        let again = load_l3repo();
+        assert_eq!(first.components(), again.components());
+        assert_eq!(first.groups(), again.groups());
+    }
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use structopt::StructOpt;
+
+use nvsdk_getter::actions::{show, verify, Action};
+use nvsdk_getter::error::Error;
+use nvsdk_getter::output::OutputFormat;
+use nvsdk_getter::sdkm_l3::L3Repo;
+
+/// The fixture with the CUDA component offered in several versions
+fn load_fixture() -> L3Repo {
+    let mut l3repo = common::load_l3repo();
+    let cuda = l3repo.components.get_mut("NV_CUDA_TOOLKIT_COMP").unwrap();
+    let template = cuda.versions[0].clone();
+    cuda.versions = ["5.0", "5.1", "5.2.1", "6.0", "r32.3"]
+        .iter()
+        .map(|version| {
+            let mut v = template.clone();
+            v.version = version.to_string();
+            v
+        })
+        .collect();
+    l3repo
+}
+
+fn selected(l3repo: &L3Repo, version: &str) -> Option<String> {
+    l3repo
+        .get_component("NV_CUDA_TOOLKIT_COMP")
+        .unwrap()
+        .selected_version(Some(version))
+        .map(|v| v.version.clone())
+}
+
+#[test]
+fn ranges_select_the_highest_matching_version() {
+    let l3repo = load_fixture();
+    assert_eq!(selected(&l3repo, ">=5.1,<6").as_deref(), Some("5.2.1"));
+    assert_eq!(selected(&l3repo, ">=5.1, <6").as_deref(), Some("5.2.1"));
+    assert_eq!(selected(&l3repo, "<5.1").as_deref(), Some("5.0"));
+    assert_eq!(selected(&l3repo, ">=5").as_deref(), Some("6.0"));
+    assert_eq!(selected(&l3repo, "~5.1").as_deref(), Some("5.1"));
+}
+
+#[test]
+fn plain_versions_match_exactly() {
+    let l3repo = load_fixture();
+    assert_eq!(selected(&l3repo, "5.1").as_deref(), Some("5.1"));
+    assert_eq!(selected(&l3repo, "r32.3").as_deref(), Some("r32.3"));
+    assert_eq!(selected(&l3repo, "5"), None);
+    assert_eq!(selected(&l3repo, ">=7"), None);
+}
+
+#[test]
+fn unmatched_range_lists_the_available_versions() {
+    let l3repo = load_fixture();
+    let action = Action::from_iter(&[
+        "nvsdk_getter",
+        "verify",
+        "-c",
+        "NV_CUDA_TOOLKIT_COMP",
+        "--version",
+        ">=7",
+    ]);
+    let dir = std::env::temp_dir().join(format!("nvsdk_getter-ranges-{}", std::process::id()));
+    match verify(&l3repo, &action, &dir, OutputFormat::Text) {
+        Err(Error::NoMatchingVersion {
+            component,
+            version,
+            available,
+        }) => {
+            assert_eq!(component, "NV_CUDA_TOOLKIT_COMP");
+            assert_eq!(version, ">=7");
+            assert_eq!(available, vec!["5.0", "5.1", "5.2.1", "6.0", "r32.3"]);
+        }
+        other => panic!("expected NoMatchingVersion, got {:?}", other),
+    }
+}
+
+#[test]
+fn show_selects_the_named_group_version() {
+    let l3repo = load_fixture();
+    let show_text = |group: &str| {
+        let action = Action::from_iter(&["nvsdk_getter", "show", "-g", group]);
+        show(&l3repo, &action, OutputFormat::Text)
+    };
+
+    show_text("CUDA:10.0").unwrap();
+    match show_text("CUDA:9.0") {
+        Err(Error::InvalidGroup(group)) => assert_eq!(group, "CUDA:9.0"),
+        other => panic!("expected InvalidGroup, got {:?}", other),
+    }
+}
+
+#[test]
+fn structured_show_selects_the_named_versions() {
+    let l3repo = load_fixture();
+    let show_json = |args: &[&str]| {
+        let mut argv = vec!["nvsdk_getter", "show"];
+        argv.extend_from_slice(args);
+        show(&l3repo, &Action::from_iter(&argv), OutputFormat::Json)
+    };
+
+    show_json(&["-c", "NV_CUDA_TOOLKIT_COMP:>=5.1,<6"]).unwrap();
+    show_json(&["-g", "CUDA:10.0"]).unwrap();
+    match show_json(&["-c", "NV_CUDA_TOOLKIT_COMP:>=7"]) {
+        Err(Error::NoMatchingVersion { version, .. }) => assert_eq!(version, ">=7"),
+        other => panic!("expected NoMatchingVersion, got {:?}", other),
+    }
+    match show_json(&["-c", "NV_CUDA_TOOLKIT_COMP", "--version", ">=7"]) {
+        Err(Error::NoMatchingVersion { version, .. }) => assert_eq!(version, ">=7"),
+        other => panic!("expected NoMatchingVersion, got {:?}", other),
+    }
+    match show_json(&["-g", "CUDA:9.0"]) {
+        Err(Error::InvalidGroup(group)) => assert_eq!(group, "CUDA:9.0"),
+        other => panic!("expected InvalidGroup, got {:?}", other),
+    }
+}